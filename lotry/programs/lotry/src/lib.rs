@@ -1,8 +1,15 @@
 use anchor_lang::prelude::*;
 use anchor_lang::system_program::{transfer, Transfer};
-use ephemeral_rollups_sdk::anchor::commit;
+use anchor_spl::token_interface::{
+    close_account, transfer_checked, CloseAccount, Mint, TokenAccount, TokenInterface,
+    TransferChecked,
+};
 use ephemeral_rollups_sdk::cpi::{delegate_account, DelegateAccounts, DelegateConfig};
-use ephemeral_rollups_sdk::ephem::commit_and_undelegate_accounts;
+use ephemeral_rollups_sdk::ephem::{commit_accounts, commit_and_undelegate_accounts};
+use ephemeral_vrf_sdk::anchor::vrf;
+use ephemeral_vrf_sdk::instructions::{create_request_randomness_ix, RequestRandomnessParams};
+use ephemeral_vrf_sdk::types::SerializableAccountMeta;
+use solana_sha256_hasher::hashv;
 
 declare_id!("6uuK1kSc5UtnDy7MzhztXQ5fPz3LA6GLwFxxTUvQzC6L");
 
@@ -12,12 +19,39 @@ declare_id!("6uuK1kSc5UtnDy7MzhztXQ5fPz3LA6GLwFxxTUvQzC6L");
 pub const LOTTERY_POOL_SEED: &[u8] = b"lottery_pool";
 pub const PLAYER_TICKET_SEED: &[u8] = b"player_ticket";
 pub const SESSION_SEED: &[u8] = b"session";
+pub const POOL_VAULT_SEED: &[u8] = b"pool_vault";
+pub const CONFIG_SEED: &[u8] = b"config";
+pub const SERIES_SEED: &[u8] = b"series";
+pub const WEIGHT_TREE_SEED: &[u8] = b"weight_tree";
+
+// ──────────────────────────────────────────────────────────────────────────────
+// Limits
+// ──────────────────────────────────────────────────────────────────────────────
+/// Entries per `PlayerTicket`. Random-ticket and weighted entries can each
+/// spend any number of credits; pick-number and Pick-N entries take one.
+pub const MAX_TICKETS_PER_PLAYER: usize = 16;
+pub const MAX_PRIZE_TIERS: usize = 8;
+pub const MAX_ALLOWED_VALIDATORS: usize = 8;
+pub const MAX_LOTTERY_CREATORS: usize = 16;
+/// Numbers per Pick-N entry; shares `winning_numbers` with per-tier draws.
+pub const MAX_PICKS: usize = MAX_PRIZE_TIERS;
+/// Entries per weighted epoch. A power of two for the Fenwick search, and
+/// small enough for the tree to be created in one instruction (10 KiB).
+pub const WEIGHT_TREE_CAPACITY: usize = 1024;
+/// `SessionScope::instructions` bits.
+pub const SESSION_SCOPE_BUY_TICKET: u8 = 1 << 0;
+pub const SESSION_SCOPE_REQUEST_RANDOMNESS: u8 = 1 << 1;
+/// Undrawn epochs become refundable this long after `draw_at`.
+pub const REFUND_TIMEOUT_SECS: i64 = 7 * 24 * 60 * 60;
+/// `winning_ticket_ids` entry of a pick-number tier no entry has matched.
+pub const NO_WINNER: u64 = u64::MAX;
 
 // ──────────────────────────────────────────────────────────────────────────────
 // TEE / ER validator pubkeys
 // ──────────────────────────────────────────────────────────────────────────────
 // TEE validator: tee.magicblock.app
-pub const TEE_VALIDATOR: &str = "MAS1Dt9qreoRMQ14YQuhg8UTZMMzDdKhmkZMECCzk57";
+// Sole allowed validator while the config allowlist is empty
+pub const TEE_VALIDATOR: Pubkey = pubkey!("MAS1Dt9qreoRMQ14YQuhg8UTZMMzDdKhmkZMECCzk57");
 pub const DELEGATION_PROGRAM_ID: &str = "DELeGGvXpWV2fqJUhqcF5ZSYMS4JTLjteaAMARRSaeSh";
 
 // ──────────────────────────────────────────────────────────────────────────────
@@ -28,90 +62,251 @@ pub const DELEGATION_PROGRAM_ID: &str = "DELeGGvXpWV2fqJUhqcF5ZSYMS4JTLjteaAMARR
 pub mod lotry {
     use super::*;
 
+    // ── Program Config ────────────────────────────────────────────────────────
+
+    /// Create the program-wide config. Only the program's upgrade authority
+    /// can call this, and it becomes the config admin.
+    pub fn init_config(ctx: Context<InitConfig>, params: ConfigParams) -> Result<()> {
+        params.validate()?;
+        let config = &mut ctx.accounts.config;
+        config.admin = ctx.accounts.admin.key();
+        config.apply(&params);
+        emit_cpi!(config.updated_event());
+        msg!(
+            "ProgramConfig initialized — admin={} protocol_fee_bps={} fee_recipient={} base_price=[{}, {}]",
+            config.admin,
+            params.protocol_fee_bps,
+            params.fee_recipient,
+            params.min_base_price,
+            params.max_base_price
+        );
+        Ok(())
+    }
+
+    /// Replace the config settings (admin-only). Pools already created keep
+    /// the protocol fee they were created with.
+    pub fn update_config(ctx: Context<UpdateConfig>, params: ConfigParams) -> Result<()> {
+        params.validate()?;
+        let config = &mut ctx.accounts.config;
+        require_keys_eq!(
+            config.admin,
+            ctx.accounts.admin.key(),
+            LottryError::Unauthorized
+        );
+        config.apply(&params);
+        emit_cpi!(config.updated_event());
+        msg!(
+            "ProgramConfig updated — protocol_fee_bps={} fee_recipient={} base_price=[{}, {}]",
+            params.protocol_fee_bps,
+            params.fee_recipient,
+            params.min_base_price,
+            params.max_base_price
+        );
+        Ok(())
+    }
+
     // ── Phase 1 ───────────────────────────────────────────────────────────────
 
-    /// Create a new lottery epoch on the base layer (L1).
+    /// Create a new lottery epoch on the base layer (L1). The caller must be
+    /// the config admin or an allowlisted lottery creator. Hand-picked ids
+    /// stay below `1 << 32`; the range above belongs to series.
     pub fn initialize_lottery(
         ctx: Context<InitializeLottery>,
         epoch_id: u64,
-        base_price: u64,
-        curve_multiplier: u64,
-        tax_rate_bps: u16,
+        params: LotteryParams,
     ) -> Result<()> {
-        require!(tax_rate_bps <= 10_000, LottryError::InvalidTaxRate);
-        let pool = &mut ctx.accounts.lottery_pool;
-        pool.authority = ctx.accounts.authority.key();
-        pool.epoch_id = epoch_id;
-        pool.ticket_count = 0;
-        pool.total_staked_sol = 0;
-        pool.tax_treasury_sol = 0;
-        pool.base_price = base_price;
-        pool.curve_multiplier = curve_multiplier;
-        pool.tax_rate_bps = tax_rate_bps;
-        pool.is_active = true;
-        pool.vrf_request_id = None;
-        pool.winner_ticket_id = None;
+        require!(epoch_id < 1 << 32, LottryError::EpochIdReserved);
+        params.validate()?;
+        let config = &ctx.accounts.config;
+        require!(
+            config.is_creator(&ctx.accounts.authority.key()),
+            LottryError::UnauthorizedCreator
+        );
+        config.require_price_in_bounds(params.base_price)?;
+
+        ctx.accounts.lottery_pool.init(
+            ctx.accounts.authority.key(),
+            ctx.accounts.authority.key(),
+            epoch_id,
+            &params,
+            config.protocol_fee_bps,
+        );
+        emit_cpi!(ctx
+            .accounts
+            .lottery_pool
+            .initialized_event(ctx.accounts.lottery_pool.key(), None));
         msg!(
-            "LotteryPool initialized — epoch {} base_price={} curve_multiplier={} tax_rate_bps={}",
+            "LotteryPool initialized — epoch {} base_price={} pricing_curve={:?} fees_bps=(purchase {}, payout {}) draw_authority={} sale=[{}, {}) draw_at={} prize_tiers={:?} claim_window={}s forfeit={:?}",
             epoch_id,
-            base_price,
-            curve_multiplier,
-            tax_rate_bps
+            params.base_price,
+            params.pricing_curve,
+            params.purchase_fee_bps,
+            params.payout_fee_bps,
+            params.draw_authority,
+            params.sale_opens_at,
+            params.sale_closes_at,
+            params.draw_at,
+            params.prize_tiers,
+            params.claim_window_secs,
+            params.forfeit_policy
+        );
+        Ok(())
+    }
+
+    // ── Series ────────────────────────────────────────────────────────────────
+
+    /// Create a lottery series: a template plus an epoch counter. Series
+    /// `n` owns epoch ids `n << 32 ..`, so ids never collide with other
+    /// series or with hand-picked ids below `1 << 32`.
+    pub fn create_series(
+        ctx: Context<CreateSeries>,
+        series_id: u32,
+        template: SeriesParams,
+    ) -> Result<()> {
+        require!(series_id > 0, LottryError::InvalidSeries);
+        template.validate()?;
+        let config = &ctx.accounts.config;
+        require!(
+            config.is_creator(&ctx.accounts.authority.key()),
+            LottryError::UnauthorizedCreator
+        );
+        config.require_price_in_bounds(template.base_price)?;
+
+        let series = &mut ctx.accounts.series;
+        series.authority = ctx.accounts.authority.key();
+        series.series_id = series_id;
+        series.last_epoch_id = (series_id as u64) << 32;
+        series.template = template;
+
+        emit_cpi!(SeriesCreated {
+            series_id,
+            authority: series.authority,
+            first_epoch_id: series.last_epoch_id + 1,
+        });
+        msg!(
+            "LotterySeries {} created — first epoch {}",
+            series_id,
+            series.last_epoch_id + 1
         );
         Ok(())
     }
 
+    /// Open the series' next epoch (`last_epoch_id + 1`) from its template,
+    /// with the sale starting now. Callable by the series authority or its
+    /// draw crank.
+    pub fn open_next_epoch(ctx: Context<OpenNextEpoch>, _series_id: u32) -> Result<()> {
+        let series = &mut ctx.accounts.series;
+        require!(
+            ctx.accounts.payer.key() == series.authority
+                || ctx.accounts.payer.key() == series.template.draw_authority,
+            LottryError::Unauthorized
+        );
+        let config = &ctx.accounts.config;
+        require!(
+            config.is_creator(&series.authority),
+            LottryError::UnauthorizedCreator
+        );
+        config.require_price_in_bounds(series.template.base_price)?;
+
+        let epoch_id = series.next_epoch_id()?;
+        let params = series.template.params_at(Clock::get()?.unix_timestamp)?;
+        params.validate()?;
+        ctx.accounts.lottery_pool.init(
+            series.authority,
+            ctx.accounts.payer.key(),
+            epoch_id,
+            &params,
+            config.protocol_fee_bps,
+        );
+        series.last_epoch_id = epoch_id;
+
+        emit_cpi!(ctx
+            .accounts
+            .lottery_pool
+            .initialized_event(ctx.accounts.lottery_pool.key(), Some(series.series_id)));
+        msg!(
+            "Series {} opened epoch {} — sale=[{}, {}) draw_at={}",
+            series.series_id,
+            epoch_id,
+            params.sale_opens_at,
+            params.sale_closes_at,
+            params.draw_at
+        );
+        Ok(())
+    }
 
     // ── Phase 2 ───────────────────────────────────────────────────────────────
 
-    /// Buy ticket credits on L1 with dynamic pricing + tax.
+    /// Buy ticket credits on L1. Each credit is priced on the pool's curve at
+    /// its position in the sale; the purchase fee is taken out of the total.
+    /// Fails rather than charging more than `max_total_price`; returns the
+    /// amount actually paid. Token pools pay from `buyer_token_account`.
+    /// Pick-number and Pick-N entries take one credit each, so there a player
+    /// holds at most `MAX_TICKETS_PER_PLAYER` credits and entries combined;
+    /// other modes only need an entry left to spend the credits on.
     pub fn buy_ticket_credits(
         ctx: Context<BuyTicketCredits>,
         epoch_id: u64,
         ticket_amount: u64,
-    ) -> Result<()> {
+        max_total_price: u64,
+    ) -> Result<u64> {
+        let mut token = resolve_token_accounts(
+            &ctx.accounts.lottery_pool,
+            &ctx.accounts.mint,
+            &mut ctx.accounts.pool_vault,
+            &ctx.accounts.buyer_token_account,
+            &ctx.accounts.token_program,
+            &ctx.accounts.buyer.key(),
+        )?;
         let pool = &mut ctx.accounts.lottery_pool;
         let player_ticket = &mut ctx.accounts.player_ticket;
 
         require!(pool.is_active, LottryError::PoolNotActive);
         require!(pool.epoch_id == epoch_id, LottryError::EpochMismatch);
+        pool.require_sale_open(Clock::get()?.unix_timestamp)?;
         require!(ticket_amount > 0, LottryError::InvalidTicketAmount);
         require_keys_eq!(
             player_ticket.owner,
             ctx.accounts.buyer.key(),
             LottryError::InvalidTicketOwner
         );
+        // Credits past the entry limit could never be spent
+        if pool.game_mode.spans_credits() {
+            require!(
+                player_ticket.entries.len() < MAX_TICKETS_PER_PLAYER,
+                LottryError::TicketLimitReached
+            );
+        } else {
+            let held = (player_ticket.entries.len() as u64)
+                .checked_add(player_ticket.balance)
+                .and_then(|held| held.checked_add(ticket_amount))
+                .ok_or(LottryError::MathOverflow)?;
+            require!(
+                held <= MAX_TICKETS_PER_PLAYER as u64,
+                LottryError::TicketLimitReached
+            );
+        }
 
-        let current_price = pool.current_price()?;
-        let total_price = (current_price as u128)
-            .checked_add(
-                (pool.curve_multiplier as u128)
-                    .checked_mul(ticket_amount as u128)
-                    .ok_or(LottryError::MathOverflow)?,
-            )
-            .ok_or(LottryError::MathOverflow)?;
-        require!(total_price <= u64::MAX as u128, LottryError::MathOverflow);
-        let total_price_u64 = total_price as u64;
-
-        let tax_amount = total_price
-            .checked_mul(pool.tax_rate_bps as u128)
-            .ok_or(LottryError::MathOverflow)?
-            / 10_000u128;
-        require!(tax_amount <= u64::MAX as u128, LottryError::MathOverflow);
-        let tax_u64 = tax_amount as u64;
-        let net_amount = total_price_u64
+        let total_price_u64 = pool.quote_credits(ticket_amount)?;
+        require!(
+            total_price_u64 <= max_total_price,
+            LottryError::PriceExceedsLimit
+        );
+
+        // Token-2022 transfer fees are withheld in flight, so fees and stake
+        // are booked on what the pool actually received.
+        let received = pay_into_pool(
+            &pool.to_account_info(),
+            &ctx.accounts.buyer.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            token.as_mut(),
+            total_price_u64,
+        )?;
+        let tax_u64 = bps_of(received, pool.purchase_fee_bps)?;
+        let net_amount = received
             .checked_sub(tax_u64)
             .ok_or(LottryError::MathOverflow)?;
 
-        let cpi_ctx = CpiContext::new(
-            ctx.accounts.system_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.buyer.to_account_info(),
-                to: pool.to_account_info(),
-            },
-        );
-        transfer(cpi_ctx, total_price_u64)?;
-
         pool.total_staked_sol = pool
             .total_staked_sol
             .checked_add(net_amount)
@@ -120,24 +315,105 @@ pub mod lotry {
             .tax_treasury_sol
             .checked_add(tax_u64)
             .ok_or(LottryError::MathOverflow)?;
+        pool.credits_sold = pool
+            .credits_sold
+            .checked_add(ticket_amount)
+            .ok_or(LottryError::MathOverflow)?;
+        pool.assert_solvent(&pool.to_account_info(), token.as_ref())?;
         player_ticket.balance = player_ticket
             .balance
             .checked_add(ticket_amount)
             .ok_or(LottryError::MathOverflow)?;
+        player_ticket.paid_sol = player_ticket
+            .paid_sol
+            .checked_add(received)
+            .ok_or(LottryError::MathOverflow)?;
+        player_ticket.paid_tax_sol = player_ticket
+            .paid_tax_sol
+            .checked_add(tax_u64)
+            .ok_or(LottryError::MathOverflow)?;
 
+        emit_cpi!(CreditsPurchased {
+            epoch_id,
+            buyer: ctx.accounts.buyer.key(),
+            ticket_amount,
+            total_price: total_price_u64,
+            received,
+            tax: tax_u64,
+        });
         msg!(
-            "Credits purchased: buyer={} tickets={} total_price={} tax={} net={}",
+            "Credits purchased: buyer={} tickets={} total_price={} received={} tax={} net={}",
             ctx.accounts.buyer.key(),
             ticket_amount,
             total_price_u64,
+            received,
             tax_u64,
             net_amount
         );
+        Ok(total_price_u64)
+    }
+
+    /// Read-only quote: exact lamports `buy_ticket_credits` would charge right
+    /// now for `ticket_amount` credits. Returned via return data.
+    pub fn quote_ticket_credits(
+        ctx: Context<QuoteTicketCredits>,
+        epoch_id: u64,
+        ticket_amount: u64,
+    ) -> Result<u64> {
+        let pool = &ctx.accounts.lottery_pool;
+        require!(pool.epoch_id == epoch_id, LottryError::EpochMismatch);
+        require!(ticket_amount > 0, LottryError::InvalidTicketAmount);
+        pool.quote_credits(ticket_amount)
+    }
+
+    /// Denominate the pool in an SPL Token or Token-2022 mint instead of SOL,
+    /// creating the pool-owned vault. Admin-only, while the pool holds nothing:
+    /// before any credits sell or any stake rolls in.
+    pub fn init_pool_vault(ctx: Context<InitPoolVault>, epoch_id: u64) -> Result<()> {
+        let pool = &mut ctx.accounts.lottery_pool;
+        require!(pool.epoch_id == epoch_id, LottryError::EpochMismatch);
+        require_keys_eq!(
+            pool.authority,
+            ctx.accounts.authority.key(),
+            LottryError::Unauthorized
+        );
+        require!(
+            pool.mint.is_none()
+                && pool.credits_sold == 0
+                && pool.total_staked_sol == 0
+                && pool.tax_treasury_sol == 0
+                && pool.rolled_in_sol == 0,
+            LottryError::DenominationLocked
+        );
+
+        pool.mint = Some(ctx.accounts.mint.key());
+        pool.token_vault = Some(ctx.accounts.pool_vault.key());
+
+        emit_cpi!(PoolDenominated {
+            epoch_id,
+            mint: ctx.accounts.mint.key(),
+            vault: ctx.accounts.pool_vault.key(),
+        });
+        msg!(
+            "Epoch {} denominated in mint {} (vault {})",
+            epoch_id,
+            ctx.accounts.mint.key(),
+            ctx.accounts.pool_vault.key()
+        );
         Ok(())
     }
 
-    /// Delegate the lottery pool to the Ephemeral Rollup validator.
+    /// Delegate the lottery pool to an allowlisted Ephemeral Rollup validator,
+    /// which the pool records so player tickets can follow it.
     pub fn delegate_lottery(ctx: Context<DelegateLottery>, epoch_id: u64) -> Result<()> {
+        let validator = ctx
+            .accounts
+            .config
+            .require_allowed_validator(ctx.accounts.validator.as_ref())?;
+        ctx.accounts.lottery_pool.validator = Some(validator);
+        // Flush the pool now: after the CPI it belongs to the delegation program
+        ctx.accounts.lottery_pool.exit(&crate::ID)?;
+
         let epoch_bytes = epoch_id.to_le_bytes();
         let seeds = &[
             LOTTERY_POOL_SEED,
@@ -150,9 +426,8 @@ pub mod lotry {
         msg!("Delegating pool: {:?}", ctx.accounts.lottery_pool.key());
         msg!("Seeds: {:?} {:?}", LOTTERY_POOL_SEED, epoch_bytes);
         
-        // Use the dynamically provided validator account
         let delegate_config = DelegateConfig {
-            validator: ctx.accounts.validator.as_ref().map(|v| *v.key),
+            validator: Some(validator),
             ..Default::default()
         };
 
@@ -172,56 +447,308 @@ pub mod lotry {
             delegate_config,
         )?;
 
-        msg!("Lottery pool for epoch {} delegated to ER", epoch_id);
+        emit_cpi!(AccountDelegated {
+            account: ctx.accounts.lottery_pool.key(),
+            epoch_id: Some(epoch_id),
+            validator,
+        });
+        msg!(
+            "Lottery pool for epoch {} delegated to ER validator {}",
+            epoch_id,
+            validator
+        );
+        Ok(())
+    }
+
+    /// Create the prefix-sum tree a weighted epoch draws from (admin-only),
+    /// before any entries and before the pool is delegated.
+    pub fn init_weight_tree(ctx: Context<InitWeightTree>, epoch_id: u64) -> Result<()> {
+        let pool = &ctx.accounts.lottery_pool;
+        require!(pool.epoch_id == epoch_id, LottryError::EpochMismatch);
+        require_keys_eq!(
+            pool.authority,
+            ctx.accounts.authority.key(),
+            LottryError::Unauthorized
+        );
+        require!(
+            pool.game_mode == GameMode::Weighted,
+            LottryError::InvalidGameMode
+        );
+        require!(pool.ticket_count == 0, LottryError::DenominationLocked);
+
+        let mut tree = ctx.accounts.weight_tree.load_init()?;
+        tree.epoch_id = epoch_id;
+
+        emit_cpi!(WeightTreeCreated {
+            epoch_id,
+            tree: ctx.accounts.weight_tree.key(),
+        });
+        msg!("WeightTree created for epoch {}", epoch_id);
+        Ok(())
+    }
+
+    /// Delegate a weighted epoch's tree to the ER validator its pool was
+    /// delegated to, so `buy_ticket` and the draw can update it there.
+    pub fn delegate_weight_tree(ctx: Context<DelegateWeightTree>, epoch_id: u64) -> Result<()> {
+        // The pool is usually already delegated, so read it without an owner check
+        let pool = LotteryPool::try_deserialize(
+            &mut &ctx.accounts.lottery_pool.try_borrow_data()?[..],
+        )?;
+        let validator = ctx
+            .accounts
+            .validator
+            .as_ref()
+            .map(|v| *v.key)
+            .ok_or(LottryError::InvalidValidator)?;
+        require!(
+            pool.validator == Some(validator),
+            LottryError::InvalidValidator
+        );
+
+        let epoch_bytes = epoch_id.to_le_bytes();
+        let pda_signer_seeds: &[&[u8]] = &[WEIGHT_TREE_SEED, &epoch_bytes];
+        let delegate_config = DelegateConfig {
+            validator: Some(validator),
+            ..Default::default()
+        };
+
+        delegate_account(
+            DelegateAccounts {
+                payer: &ctx.accounts.authority.to_account_info(),
+                pda: &ctx.accounts.weight_tree.to_account_info(),
+                owner_program: &ctx.accounts.owner_program,
+                buffer: &ctx.accounts.buffer_weight_tree,
+                delegation_record: &ctx.accounts.delegation_record,
+                delegation_metadata: &ctx.accounts.delegation_metadata,
+                delegation_program: &ctx.accounts.delegation_program,
+                system_program: &ctx.accounts.system_program.to_account_info(),
+            },
+            pda_signer_seeds,
+            delegate_config,
+        )?;
+
+        emit_cpi!(AccountDelegated {
+            account: ctx.accounts.weight_tree.key(),
+            epoch_id: Some(epoch_id),
+            validator,
+        });
+        msg!("WeightTree of epoch {} delegated to ER validator {}", epoch_id, validator);
         Ok(())
     }
 
     // ── Phase 3 ───────────────────────────────────────────────────────────────
 
     /// Issue a session key for frictionless high-frequency ticket purchases.
-    /// Must be signed by the user's primary wallet.
+    /// Must be signed by the user's primary wallet. `scope` limits what the
+    /// key may do; delegate the token before using it in `buy_ticket`.
     pub fn issue_session(
         ctx: Context<IssueSession>,
         ephemeral_key: Pubkey,
         valid_until: i64,
+        scope: SessionScope,
     ) -> Result<()> {
-        require!(
-            valid_until > Clock::get()?.unix_timestamp,
-            LottryError::InvalidExpiry
-        );
+        let now = Clock::get()?.unix_timestamp;
+        require!(valid_until > now, LottryError::InvalidExpiry);
+        ctx.accounts
+            .config
+            .require_session_lifetime(valid_until, now)?;
+        scope.validate()?;
         let session = &mut ctx.accounts.session_token;
         session.authority = ctx.accounts.authority.key();
         session.ephemeral_key = ephemeral_key;
         session.valid_until = valid_until;
+        session.is_revoked = false;
+        session.credits_remaining = scope.max_credits;
+        session.scope = scope;
+        emit_cpi!(SessionIssued {
+            authority: session.authority,
+            ephemeral_key,
+            valid_until,
+            scope: session.scope,
+        });
         msg!(
-            "SessionToken issued: ephemeral_key={} valid_until={}",
+            "SessionToken issued: ephemeral_key={} valid_until={} scope={:?}",
             ephemeral_key,
+            valid_until,
+            session.scope
+        );
+        Ok(())
+    }
+
+    /// Extend an existing session to `valid_until`, keeping its key, scope
+    /// and remaining allowance. Capped like `issue_session`; revoked sessions
+    /// cannot be renewed.
+    pub fn renew_session(ctx: Context<RenewSession>, valid_until: i64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let session = &mut ctx.accounts.session_token;
+        require!(!session.is_revoked, LottryError::SessionRevoked);
+        require!(
+            valid_until > now && valid_until > session.valid_until,
+            LottryError::InvalidExpiry
+        );
+        ctx.accounts
+            .config
+            .require_session_lifetime(valid_until, now)?;
+
+        session.valid_until = valid_until;
+        emit_cpi!(SessionRenewed {
+            authority: session.authority,
+            ephemeral_key: session.ephemeral_key,
+            valid_until,
+        });
+        msg!(
+            "SessionToken renewed: ephemeral_key={} valid_until={}",
+            session.ephemeral_key,
             valid_until
         );
         Ok(())
     }
 
+    /// Delegate a session token to the ER so `buy_ticket` can draw down its
+    /// credit allowance there.
+    pub fn delegate_session(ctx: Context<DelegateSession>) -> Result<()> {
+        let validator = ctx
+            .accounts
+            .config
+            .require_allowed_validator(ctx.accounts.validator.as_ref())?;
+        let auth_key = ctx.accounts.authority.key();
+        let ephemeral_key = ctx.accounts.session_token.ephemeral_key;
+        let pda_signer_seeds: &[&[u8]] = &[
+            SESSION_SEED,
+            auth_key.as_ref(),
+            ephemeral_key.as_ref(),
+        ];
+
+        let delegate_config = DelegateConfig {
+            validator: Some(validator),
+            ..Default::default()
+        };
+
+        delegate_account(
+            DelegateAccounts {
+                payer: &ctx.accounts.authority.to_account_info(),
+                pda: &ctx.accounts.session_token.to_account_info(),
+                owner_program: &ctx.accounts.owner_program,
+                buffer: &ctx.accounts.buffer_session_token,
+                delegation_record: &ctx.accounts.delegation_record,
+                delegation_metadata: &ctx.accounts.delegation_metadata,
+                delegation_program: &ctx.accounts.delegation_program,
+                system_program: &ctx.accounts.system_program.to_account_info(),
+            },
+            pda_signer_seeds,
+            delegate_config,
+        )?;
+
+        emit_cpi!(AccountDelegated {
+            account: ctx.accounts.session_token.key(),
+            epoch_id: None,
+            validator,
+        });
+        msg!("SessionToken {} delegated to ER", ephemeral_key);
+        Ok(())
+    }
+
+    /// Commit a session token back to L1 and undelegate it, e.g. before
+    /// `close_session`. Runs on the ER.
+    pub fn undelegate_session(ctx: Context<UndelegateSession>) -> Result<()> {
+        commit_and_undelegate_accounts(
+            &ctx.accounts.authority,
+            vec![&ctx.accounts.session_token.to_account_info()],
+            &ctx.accounts.magic_context,
+            &ctx.accounts.magic_program,
+        )?;
+        emit_cpi!(AccountsUndelegated {
+            epoch_id: None,
+            count: 1,
+        });
+        msg!(
+            "SessionToken {} committed & undelegated",
+            ctx.accounts.session_token.ephemeral_key
+        );
+        Ok(())
+    }
+
+    /// Revoke a session key before it expires, e.g. after it leaked. Runs
+    /// wherever the token lives: on the ER once delegated, otherwise on L1,
+    /// where the ER reads the same copy.
+    pub fn revoke_session(ctx: Context<RevokeSession>) -> Result<()> {
+        let session = &mut ctx.accounts.session_token;
+        require!(!session.is_revoked, LottryError::SessionRevoked);
+        session.is_revoked = true;
+        session.valid_until = session.valid_until.min(Clock::get()?.unix_timestamp);
+        emit_cpi!(SessionRevoked {
+            authority: session.authority,
+            ephemeral_key: session.ephemeral_key,
+        });
+        msg!("SessionToken revoked: ephemeral_key={}", session.ephemeral_key);
+        Ok(())
+    }
+
+    /// Close an expired or revoked session token and return its rent to the
+    /// authority.
+    pub fn close_session(ctx: Context<CloseSession>) -> Result<()> {
+        let session = &ctx.accounts.session_token;
+        require!(
+            session.is_revoked || Clock::get()?.unix_timestamp >= session.valid_until,
+            LottryError::SessionStillValid
+        );
+        emit_cpi!(SessionClosed {
+            authority: session.authority,
+            ephemeral_key: session.ephemeral_key,
+        });
+        msg!("SessionToken closed: ephemeral_key={}", session.ephemeral_key);
+        Ok(())
+    }
+
     // ── Phase 4: ER Ticket Purchase ──────────────────────────────────────────
 
     /// Pre-allocates the PlayerTicket on L1 so the ER doesn't have to CPI to SystemProgram
     pub fn init_player_ticket(ctx: Context<InitPlayerTicket>, epoch_id: u64) -> Result<()> {
+        let pool = &mut ctx.accounts.lottery_pool;
+        pool.open_tickets = pool
+            .open_tickets
+            .checked_add(1)
+            .ok_or(LottryError::MathOverflow)?;
+
         let ticket = &mut ctx.accounts.player_ticket;
         ticket.owner = ctx.accounts.authority.key();
         ticket.epoch_id = epoch_id;
-        ticket.ticket_id = 0;
-        ticket.ticket_data = [0u8; 32];
+        ticket.entries = Vec::new();
         ticket.balance = 0;
+        ticket.paid_sol = 0;
+        ticket.paid_tax_sol = 0;
         ticket.is_active = false;
+        ticket.claimed_tiers = 0;
 
+        emit_cpi!(PlayerTicketCreated {
+            epoch_id,
+            owner: ticket.owner,
+        });
         msg!("PlayerTicket pre-allocated on L1 for {}", ticket.owner);
         Ok(())
     }
 
-    /// Delegates the pre-allocated PlayerTicket to the ER
+    /// Delegates the pre-allocated PlayerTicket to the ER validator its
+    /// lottery pool was delegated to.
     pub fn delegate_player_ticket(
         ctx: Context<DelegatePlayerTicket>,
         epoch_id: u64,
     ) -> Result<()> {
+        // The pool is usually already delegated, so read it without an owner check
+        let pool = LotteryPool::try_deserialize(
+            &mut &ctx.accounts.lottery_pool.try_borrow_data()?[..],
+        )?;
+        let validator = ctx
+            .accounts
+            .validator
+            .as_ref()
+            .map(|v| *v.key)
+            .ok_or(LottryError::InvalidValidator)?;
+        require!(
+            pool.validator == Some(validator),
+            LottryError::InvalidValidator
+        );
+
         let auth_key = ctx.accounts.authority.key();
         let epoch_id_bytes = epoch_id.to_le_bytes();
         let pda_signer_seeds: &[&[u8]] = &[
@@ -231,7 +758,7 @@ pub mod lotry {
         ];
 
         let delegate_config = ephemeral_rollups_sdk::cpi::DelegateConfig {
-            validator: ctx.accounts.validator.as_ref().map(|v| *v.key),
+            validator: Some(validator),
             ..Default::default()
         };
 
@@ -252,63 +779,117 @@ pub mod lotry {
             delegate_config,
         )?;
 
-        msg!("PlayerTicket delegated to ER");
+        emit_cpi!(AccountDelegated {
+            account: ctx.accounts.player_ticket.key(),
+            epoch_id: Some(epoch_id),
+            validator,
+        });
+        msg!("PlayerTicket delegated to ER validator {}", validator);
         Ok(())
     }
 
     /// Executed on the Ephemeral Rollup (ER). Uses pre-allocated L1 PlayerTicket.
     /// Signed only by the ephemeral session key — no SOL transfer (gasless on ER).
-    /// Consumes one pre-paid credit from PlayerTicket.balance.
+    /// Consumes `credits` pre-paid credits from PlayerTicket.balance per entry:
+    /// the entry's weight in weighted pools, a run of that many ticket ids in
+    /// random-ticket pools, and always one otherwise.
+    /// In pick-number pools `ticket_data` is `ticket_commitment(number, salt)`;
+    /// in Pick-N pools it holds the picked numbers (see `GameMode::PickN`).
     pub fn buy_ticket(
         ctx: Context<BuyTicket>,
         epoch_id: u64,
         ticket_data: [u8; 32],
+        credits: u64,
     ) -> Result<()> {
         // Validate session token (standard Anchor accounts, ER remaps ownership)
-        let session = &ctx.accounts.session_token;
+        let session = &mut ctx.accounts.session_token;
 
-        // Validate signer and expiry
-        require!(
-            session.ephemeral_key == ctx.accounts.ephemeral_signer.key(),
-            LottryError::InvalidSessionSigner
-        );
-        require!(
-            Clock::get()?.unix_timestamp < session.valid_until,
-            LottryError::SessionExpired
-        );
+        // Validate signer, expiry and revocation
+        session.require_usable(
+            &ctx.accounts.ephemeral_signer.key(),
+            Clock::get()?.unix_timestamp,
+        )?;
         require_keys_eq!(
             session.authority,
             ctx.accounts.authority.key(),
             LottryError::InvalidSessionSigner
         );
+        session.require_scope(
+            SESSION_SCOPE_BUY_TICKET,
+            epoch_id,
+            &ctx.accounts.lottery_pool.key(),
+        )?;
 
         let pool = &mut ctx.accounts.lottery_pool;
         let ticket = &mut ctx.accounts.player_ticket;
 
         require!(pool.is_active, LottryError::PoolNotActive);
         require!(pool.epoch_id == epoch_id, LottryError::EpochMismatch);
+        pool.require_sale_open(Clock::get()?.unix_timestamp)?;
         require_keys_eq!(ticket.owner, session.authority, LottryError::InvalidTicketOwner);
-        require!(ticket.balance > 0, LottryError::InsufficientCredits);
+        require!(ticket.epoch_id == epoch_id, LottryError::EpochMismatch);
         require!(
-            !(ticket.is_active && ticket.epoch_id == epoch_id),
-            LottryError::TicketAlreadyActive
+            credits == 1 || (credits > 1 && pool.game_mode.spans_credits()),
+            LottryError::InvalidTicketAmount
         );
+        require!(ticket.balance >= credits, LottryError::InsufficientCredits);
+        require!(
+            ticket.entries.len() < MAX_TICKETS_PER_PLAYER,
+            LottryError::TicketLimitReached
+        );
+        // The last entry must take every credit left, or they are stranded
+        require!(
+            ticket.entries.len() + 1 < MAX_TICKETS_PER_PLAYER || credits == ticket.balance,
+            LottryError::TicketLimitReached
+        );
+        pool.game_mode.require_valid_ticket_data(&ticket_data)?;
 
-        ticket.owner = session.authority;
-        ticket.epoch_id = epoch_id;
-        ticket.ticket_id = pool.ticket_count;
-        ticket.ticket_data = ticket_data;
+        // Random-ticket entries draw as a run of `credits` consecutive ids
+        let ticket_id = pool.ticket_count;
+        let count = if pool.game_mode == GameMode::RandomTicket {
+            credits
+        } else {
+            1
+        };
+        if pool.game_mode == GameMode::Weighted {
+            let tree = ctx
+                .accounts
+                .weight_tree
+                .as_ref()
+                .ok_or(LottryError::MissingWeightTree)?;
+            tree.load_mut()?.add(ticket_id, credits)?;
+        }
+        ticket.entries.push(TicketEntry {
+            ticket_id,
+            ticket_data,
+            revealed: None,
+            matches: None,
+            weight: credits,
+            count,
+        });
         ticket.is_active = true;
         ticket.balance = ticket
             .balance
-            .checked_sub(1)
+            .checked_sub(credits)
             .ok_or(LottryError::MathOverflow)?;
+        session.spend_credits(credits)?;
 
-        pool.ticket_count = pool.ticket_count.saturating_add(1);
+        pool.ticket_count = pool
+            .ticket_count
+            .checked_add(count)
+            .ok_or(LottryError::MathOverflow)?;
 
+        emit_cpi!(TicketIssued {
+            epoch_id,
+            ticket_id,
+            owner: session.authority,
+            ephemeral_key: session.ephemeral_key,
+            weight: credits,
+            count,
+        });
         msg!(
             "Ticket #{} issued to {} in epoch {}",
-            ticket.ticket_id,
+            ticket_id,
             session.authority,
             epoch_id
         );
@@ -317,64 +898,261 @@ pub mod lotry {
 
     // ── Phase 5 ───────────────────────────────────────────────────────────────
 
-    /// Simple timestamp-based winner selection. Runs on ER with session key auth.
-    pub fn request_winner(
-        ctx: Context<RequestWinner>,
+    /// Request VRF randomness for the draw. Runs on ER, never before `draw_at`.
+    /// The pool authority or crank may draw (directly or through a session
+    /// key); anyone else may draw once the pool's `draw_grace_secs` have
+    /// passed after it.
+    /// Closes ticket sales; the winner is set by `consume_randomness`.
+    pub fn request_randomness(
+        ctx: Context<RequestRandomness>,
         epoch_id: u64,
         client_seed: u8,
     ) -> Result<()> {
-        let pool = &mut ctx.accounts.lottery_pool;
-        let session = &ctx.accounts.session_token;
+        let now = Clock::get()?.unix_timestamp;
+        let pool = &ctx.accounts.lottery_pool;
+        require!(now >= pool.draw_at, LottryError::DrawTimeNotReached);
 
-        // Validate signer and expiry
-        require!(
-            session.ephemeral_key == ctx.accounts.ephemeral_signer.key(),
-            LottryError::InvalidSessionSigner
-        );
+        match &ctx.accounts.session_token {
+            Some(session) => {
+                // Validate signer, expiry and revocation
+                let ephemeral_signer = ctx
+                    .accounts
+                    .ephemeral_signer
+                    .as_ref()
+                    .ok_or(LottryError::InvalidSessionSigner)?;
+                session.require_usable(&ephemeral_signer.key(), now)?;
+                session.require_scope(SESSION_SCOPE_REQUEST_RANDOMNESS, epoch_id, &pool.key())?;
+                // Session keys only carry the draw right of the authority or crank
+                require!(
+                    pool.is_draw_authority(&session.authority),
+                    LottryError::UnauthorizedDrawer
+                );
+            }
+            None => {
+                if !pool.is_draw_authority(&ctx.accounts.payer.key()) {
+                    let permissionless_at = pool
+                        .draw_at
+                        .checked_add(pool.draw_grace_secs)
+                        .ok_or(LottryError::MathOverflow)?;
+                    require!(now >= permissionless_at, LottryError::DrawTooEarly);
+                }
+            }
+        }
+
+        let pool = &mut ctx.accounts.lottery_pool;
         require!(
-            Clock::get()?.unix_timestamp < session.valid_until,
-            LottryError::SessionExpired
-        );
-        require_keys_eq!(
-            session.authority,
-            ctx.accounts.authority.key(),
-            LottryError::InvalidSessionSigner
+            pool.vrf_request_id.is_none(),
+            LottryError::RandomnessAlreadyRequested
         );
-
         require!(pool.is_active, LottryError::PoolNotActive);
+        require!(pool.epoch_id == epoch_id, LottryError::EpochMismatch);
         require!(pool.ticket_count > 0, LottryError::NoTickets);
 
-        // Pseudo-randomness using timestamp
-        let timestamp = Clock::get()?.unix_timestamp as u64;
-        let winner_id = timestamp.wrapping_add(client_seed as u64) % pool.ticket_count;
+        // The caller seed doubles as the request id recorded on the pool.
+        let pool_key = pool.key();
+        let caller_seed = hashv(&[
+            pool_key.as_ref(),
+            &pool.ticket_count.to_le_bytes(),
+            &Clock::get()?.slot.to_le_bytes(),
+            &[client_seed],
+        ])
+        .to_bytes();
 
-        pool.winner_ticket_id = Some(winner_id);
+        pool.vrf_request_id = Some(Pubkey::new_from_array(caller_seed));
         pool.is_active = false;
 
+        // Weighted draws read the epoch's tree; other modes fill the optional
+        // slot with the program id
+        let weight_tree = match pool.game_mode {
+            GameMode::Weighted => {
+                Pubkey::find_program_address(&[WEIGHT_TREE_SEED, &epoch_id.to_le_bytes()], &crate::ID).0
+            }
+            _ => crate::ID,
+        };
+
+        let ix = create_request_randomness_ix(RequestRandomnessParams {
+            payer: ctx.accounts.payer.key(),
+            oracle_queue: ctx.accounts.oracle_queue.key(),
+            callback_program_id: crate::ID,
+            callback_discriminator: instruction::ConsumeRandomness::DISCRIMINATOR.to_vec(),
+            caller_seed,
+            // The callback emits through the program's event authority
+            accounts_metas: Some(vec![
+                SerializableAccountMeta {
+                    pubkey: pool_key,
+                    is_signer: false,
+                    is_writable: true,
+                },
+                SerializableAccountMeta {
+                    pubkey: weight_tree,
+                    is_signer: false,
+                    is_writable: weight_tree != crate::ID,
+                },
+                SerializableAccountMeta {
+                    pubkey: ctx.accounts.event_authority.key(),
+                    is_signer: false,
+                    is_writable: false,
+                },
+                SerializableAccountMeta {
+                    pubkey: crate::ID,
+                    is_signer: false,
+                    is_writable: false,
+                },
+            ]),
+            ..Default::default()
+        });
+        ctx.accounts
+            .invoke_signed_vrf(&ctx.accounts.payer.to_account_info(), &ix)?;
+
+        emit_cpi!(RandomnessRequested {
+            epoch_id,
+            request_id: Pubkey::new_from_array(caller_seed),
+            requested_by: ctx.accounts.payer.key(),
+        });
         msg!(
-            "Winner ticket #{} selected for epoch {}",
-            winner_id,
-            pool.epoch_id
+            "Randomness requested for epoch {}: request_id={}",
+            epoch_id,
+            Pubkey::new_from_array(caller_seed)
         );
         Ok(())
     }
 
-    // ── Phase 6 ───────────────────────────────────────────────────────────────
-
-    /// Commit final state to L1 and undelegate the LotteryPool from the ER.
-    pub fn undelegate_pool<'info>(
-        ctx: Context<'_, '_, '_, 'info, UndelegatePool<'info>>,
-        _epoch_id: u64,
+    /// VRF callback. Only the VRF program identity can sign this.
+    pub fn consume_randomness(
+        ctx: Context<ConsumeRandomness>,
+        randomness: [u8; 32],
     ) -> Result<()> {
-        require!(!ctx.accounts.lottery_pool.is_active, LottryError::PoolStillActive);
+        let pool = &mut ctx.accounts.lottery_pool;
 
-        let pool_info = ctx.accounts.lottery_pool.to_account_info();
-        require!(pool_info.is_writable, LottryError::AccountNotWritable);
+        require!(!pool.is_cancelled, LottryError::EpochCancelled);
+        require!(
+            pool.vrf_request_id.is_some(),
+            LottryError::RandomnessNotRequested
+        );
+        require!(!pool.is_drawn(), LottryError::WinnerAlreadySet);
 
-        let mut accounts_to_commit: Vec<&AccountInfo> = vec![&pool_info];
-        for account in ctx.remaining_accounts.iter() {
-            require!(account.is_writable, LottryError::AccountNotWritable);
-            accounts_to_commit.push(account);
+        match pool.game_mode {
+            GameMode::RandomTicket => {
+                pool.winning_ticket_ids = pool.draw_winners(&randomness)?;
+            }
+            GameMode::Weighted => {
+                let tree = ctx
+                    .accounts
+                    .weight_tree
+                    .as_ref()
+                    .ok_or(LottryError::MissingWeightTree)?;
+                let winners = pool.prize_tiers.len().min(pool.ticket_count as usize);
+                pool.winning_ticket_ids = tree.load_mut()?.draw_winners(&randomness, winners)?;
+            }
+            GameMode::PickNumber { max_number } => {
+                // Tiers go to the entries that reveal the drawn numbers
+                pool.winning_numbers =
+                    draw_numbers(&randomness, pool.prize_tiers.len(), max_number);
+                pool.winning_ticket_ids = vec![NO_WINNER; pool.winning_numbers.len()];
+            }
+            GameMode::PickN { picks, max_number } => {
+                // Tickets register their matches against the drawn combination
+                pool.winning_numbers =
+                    draw_numbers(&randomness, picks as usize, max_number as u32);
+                pool.tier_winners = vec![0; pool.prize_tiers.len()];
+                pool.tier_claims = vec![0; pool.prize_tiers.len()];
+            }
+        }
+        // Tiers are paid as shares of the prize pool as it stands at the draw
+        pool.prize_pool_sol = pool.total_staked_sol;
+        pool.reveal_deadline = Clock::get()?
+            .unix_timestamp
+            .checked_add(pool.reveal_window_secs)
+            .ok_or(LottryError::MathOverflow)?;
+        pool.claim_deadline = pool
+            .reveal_deadline
+            .checked_add(pool.claim_window_secs)
+            .ok_or(LottryError::MathOverflow)?;
+
+        emit_cpi!(WinnerSelected {
+            epoch_id: pool.epoch_id,
+            winning_ticket_ids: pool.winning_ticket_ids.clone(),
+            winning_numbers: pool.winning_numbers.clone(),
+            prize_pool: pool.prize_pool_sol,
+            claim_deadline: pool.claim_deadline,
+        });
+        msg!(
+            "Winning tickets {:?} selected for epoch {} (prize pool {}, claim by {})",
+            pool.winning_ticket_ids,
+            pool.epoch_id,
+            pool.prize_pool_sol,
+            pool.claim_deadline
+        );
+        Ok(())
+    }
+
+    // ── Phase 6 ───────────────────────────────────────────────────────────────
+
+    /// Commit the pool's current ER state to L1 while it stays delegated, so
+    /// L1 observers see live `ticket_count` during long epochs. Player tickets
+    /// of this epoch passed as remaining accounts, and a weighted epoch's
+    /// tree, are committed with it.
+    pub fn checkpoint_pool<'info>(
+        ctx: Context<'_, '_, '_, 'info, CheckpointPool<'info>>,
+        epoch_id: u64,
+    ) -> Result<()> {
+        require!(
+            ctx.accounts.lottery_pool.epoch_id == epoch_id,
+            LottryError::EpochMismatch
+        );
+        let pool_info = ctx.accounts.lottery_pool.to_account_info();
+        require!(pool_info.is_writable, LottryError::AccountNotWritable);
+
+        let tree_info = ctx.accounts.weight_tree.as_ref().map(|t| t.to_account_info());
+
+        let mut accounts_to_commit: Vec<&AccountInfo> = vec![&pool_info];
+        accounts_to_commit.extend(tree_info.as_ref());
+        for account in ctx.remaining_accounts.iter() {
+            require_player_ticket(account, epoch_id)?;
+            accounts_to_commit.push(account);
+        }
+
+        commit_accounts(
+            &ctx.accounts.payer,
+            accounts_to_commit,
+            &ctx.accounts.magic_context,
+            &ctx.accounts.magic_program,
+        )?;
+
+        emit_cpi!(PoolCommitted {
+            epoch_id,
+            ticket_count: ctx.accounts.lottery_pool.ticket_count,
+            player_tickets: ctx.remaining_accounts.len() as u32,
+            undelegated: false,
+        });
+        msg!(
+            "LotteryPool epoch {} checkpointed (ticket_count={}, {} player tickets)",
+            epoch_id,
+            ctx.accounts.lottery_pool.ticket_count,
+            ctx.remaining_accounts.len()
+        );
+        Ok(())
+    }
+
+    /// Commit final state to L1 and undelegate the LotteryPool from the ER.
+    /// Player tickets of this epoch passed as remaining accounts, and a
+    /// weighted epoch's tree, go with it.
+    pub fn undelegate_pool<'info>(
+        ctx: Context<'_, '_, '_, 'info, UndelegatePool<'info>>,
+        epoch_id: u64,
+    ) -> Result<()> {
+        require!(!ctx.accounts.lottery_pool.is_active, LottryError::PoolStillActive);
+
+        let pool_info = ctx.accounts.lottery_pool.to_account_info();
+        require!(pool_info.is_writable, LottryError::AccountNotWritable);
+
+        let tree_info = ctx.accounts.weight_tree.as_ref().map(|t| t.to_account_info());
+
+        let mut accounts_to_commit: Vec<&AccountInfo> = vec![&pool_info];
+        accounts_to_commit.extend(tree_info.as_ref());
+        for account in ctx.remaining_accounts.iter() {
+            require_player_ticket(account, epoch_id)?;
+            accounts_to_commit.push(account);
         }
 
         commit_and_undelegate_accounts(
@@ -384,6 +1162,12 @@ pub mod lotry {
             &ctx.accounts.magic_program,
         )?;
 
+        emit_cpi!(PoolCommitted {
+            epoch_id,
+            ticket_count: ctx.accounts.lottery_pool.ticket_count,
+            player_tickets: ctx.remaining_accounts.len() as u32,
+            undelegated: true,
+        });
         msg!(
             "LotteryPool epoch {} committed & undelegated",
             ctx.accounts.lottery_pool.epoch_id
@@ -391,60 +1175,267 @@ pub mod lotry {
         Ok(())
     }
 
+    /// Commit and undelegate the caller's own PlayerTicket, e.g. so a winner
+    /// can `claim_prize` on L1. Runs on the ER; only the ticket owner signs.
+    pub fn undelegate_player_ticket(
+        ctx: Context<UndelegatePlayerTicket>,
+        epoch_id: u64,
+    ) -> Result<()> {
+        let ticket = &ctx.accounts.player_ticket;
+        require!(ticket.epoch_id == epoch_id, LottryError::EpochMismatch);
+
+        commit_and_undelegate_accounts(
+            &ctx.accounts.owner,
+            vec![&ticket.to_account_info()],
+            &ctx.accounts.magic_context,
+            &ctx.accounts.magic_program,
+        )?;
+
+        emit_cpi!(AccountsUndelegated {
+            epoch_id: Some(epoch_id),
+            count: 1,
+        });
+        msg!(
+            "PlayerTicket of {} for epoch {} committed & undelegated",
+            ticket.owner,
+            epoch_id
+        );
+        Ok(())
+    }
+
+    /// Batched `undelegate_player_ticket` for cranks: once the epoch's sales
+    /// are over anyone may return its player tickets, passed as remaining
+    /// accounts and each checked to be a PlayerTicket of this epoch.
+    pub fn undelegate_player_tickets<'info>(
+        ctx: Context<'_, '_, '_, 'info, UndelegatePlayerTickets<'info>>,
+        epoch_id: u64,
+    ) -> Result<()> {
+        let pool = &ctx.accounts.lottery_pool;
+        require!(pool.epoch_id == epoch_id, LottryError::EpochMismatch);
+        require!(!pool.is_active, LottryError::PoolStillActive);
+        require!(
+            !ctx.remaining_accounts.is_empty(),
+            LottryError::InvalidPlayerTicket
+        );
+
+        let mut accounts_to_commit: Vec<&AccountInfo> = Vec::new();
+        for account in ctx.remaining_accounts.iter() {
+            require_player_ticket(account, epoch_id)?;
+            accounts_to_commit.push(account);
+        }
+
+        commit_and_undelegate_accounts(
+            &ctx.accounts.payer,
+            accounts_to_commit,
+            &ctx.accounts.magic_context,
+            &ctx.accounts.magic_program,
+        )?;
+
+        emit_cpi!(AccountsUndelegated {
+            epoch_id: Some(epoch_id),
+            count: ctx.remaining_accounts.len() as u32,
+        });
+        msg!(
+            "{} PlayerTickets for epoch {} committed & undelegated",
+            ctx.remaining_accounts.len(),
+            epoch_id
+        );
+        Ok(())
+    }
+
     // ── Phase 7 ───────────────────────────────────────────────────────────────
 
-    /// Claim the prize on L1 after the pool is undelegated.
-    pub fn claim_prize(ctx: Context<ClaimPrize>, epoch_id: u64) -> Result<()> {
+    /// Reveal the number behind a pick-number entry on L1, before
+    /// `reveal_deadline`. The entry wins every tier whose drawn number it
+    /// matches; on ties the lowest ticket id wins, whatever the reveal order.
+    pub fn reveal_ticket(
+        ctx: Context<RevealTicket>,
+        epoch_id: u64,
+        ticket_id: u64,
+        number: u32,
+        salt: [u8; 32],
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.lottery_pool;
+        let ticket = &mut ctx.accounts.player_ticket;
+
+        require!(pool.epoch_id == epoch_id, LottryError::EpochMismatch);
+        let GameMode::PickNumber { max_number } = pool.game_mode else {
+            return err!(LottryError::InvalidGameMode);
+        };
+        require!(pool.is_drawn(), LottryError::WinnerNotSet);
+        require!(
+            Clock::get()?.unix_timestamp < pool.reveal_deadline,
+            LottryError::RevealWindowClosed
+        );
+
+        let entry = ticket
+            .entries
+            .iter_mut()
+            .find(|e| e.ticket_id == ticket_id)
+            .ok_or(LottryError::InvalidReveal)?;
+        require!(entry.revealed.is_none(), LottryError::TicketAlreadyRevealed);
+        require!(
+            ticket_commitment(number, &salt) == entry.ticket_data
+                && (1..=max_number).contains(&number),
+            LottryError::InvalidReveal
+        );
+        entry.revealed = Some(number);
+        pool.record_reveal(ticket_id, number);
+
+        emit_cpi!(TicketRevealed {
+            epoch_id,
+            ticket_id,
+            owner: ticket.owner,
+            number,
+        });
+        msg!(
+            "Ticket #{} of {} revealed {} in epoch {}",
+            ticket_id,
+            ticket.owner,
+            number,
+            epoch_id
+        );
+        Ok(())
+    }
+
+    /// Count the matches of every Pick-N entry on the caller's ticket against
+    /// the drawn combination, on L1 before `reveal_deadline`. Each winning
+    /// entry adds to its tier's winner count, which splits the tier's share.
+    pub fn register_ticket(ctx: Context<RegisterTicket>, epoch_id: u64) -> Result<()> {
+        let pool = &mut ctx.accounts.lottery_pool;
+        let ticket = &mut ctx.accounts.player_ticket;
+
+        require!(pool.epoch_id == epoch_id, LottryError::EpochMismatch);
+        let GameMode::PickN { picks, .. } = pool.game_mode else {
+            return err!(LottryError::InvalidGameMode);
+        };
+        require!(pool.is_drawn(), LottryError::WinnerNotSet);
+        require!(
+            Clock::get()?.unix_timestamp < pool.reveal_deadline,
+            LottryError::RevealWindowClosed
+        );
+        require!(!ticket.entries.is_empty(), LottryError::TicketNotActive);
+
+        let mut matches: Vec<u8> = Vec::new();
+        for entry in ticket.entries.iter_mut().filter(|e| e.matches.is_none()) {
+            let matched = pool.register_entry(&entry.ticket_data, picks)?;
+            entry.matches = Some(matched);
+            matches.push(matched);
+        }
+        require!(!matches.is_empty(), LottryError::TicketAlreadyRegistered);
+
+        msg!(
+            "Ticket of {} registered in epoch {}: matches {:?}",
+            ticket.owner,
+            epoch_id,
+            matches
+        );
+        // Emitted last: the event takes ownership of `matches`
+        emit_cpi!(TicketRegistered {
+            epoch_id,
+            owner: ticket.owner,
+            matches,
+        });
+        Ok(())
+    }
+
+    /// Claim one prize tier on L1 after the pool is undelegated, before
+    /// `claim_deadline`. Each tier is claimed independently by whoever holds
+    /// its winning ticket; in Pick-N pools every registered entry with the
+    /// tier's match count is paid its split of the tier at once.
+    pub fn claim_prize(ctx: Context<ClaimPrize>, epoch_id: u64, tier: u8) -> Result<()> {
+        let mut token = resolve_token_accounts(
+            &ctx.accounts.lottery_pool,
+            &ctx.accounts.mint,
+            &mut ctx.accounts.pool_vault,
+            &ctx.accounts.winner_token_account,
+            &ctx.accounts.token_program,
+            &ctx.accounts.winner.key(),
+        )?;
         let pool = &mut ctx.accounts.lottery_pool;
         let ticket = &mut ctx.accounts.player_ticket;
 
         require!(!pool.is_active, LottryError::PoolStillActive);
         require!(pool.epoch_id == epoch_id, LottryError::EpochMismatch);
-        let winner_id = pool.winner_ticket_id.ok_or(LottryError::WinnerNotSet)?;
+        require!(pool.is_drawn(), LottryError::WinnerNotSet);
+        let now = Clock::get()?.unix_timestamp;
+        require!(now >= pool.reveal_deadline, LottryError::RevealWindowOpen);
+        require!(now < pool.claim_deadline, LottryError::ClaimDeadlinePassed);
         require!(
             ticket.is_active && ticket.epoch_id == epoch_id,
             LottryError::TicketNotActive
         );
-        require!(ticket.ticket_id == winner_id, LottryError::NotWinner);
         require_keys_eq!(
             ticket.owner,
             ctx.accounts.winner.key(),
             LottryError::InvalidTicketOwner
         );
 
-        let total_staked = pool.total_staked_sol;
-        require!(total_staked > 0, LottryError::NoStakedFunds);
+        let (share, winner_id, entries) = match pool.game_mode {
+            GameMode::PickN { picks, .. } => pool.claim_pick_n_tier(ticket, tier, picks)?,
+            GameMode::RandomTicket | GameMode::PickNumber { .. } | GameMode::Weighted => {
+                let winner_id = *pool
+                    .winning_ticket_ids
+                    .get(tier as usize)
+                    .ok_or(LottryError::InvalidPrizeTier)?;
+                require!(!pool.is_tier_claimed(tier), LottryError::PrizeAlreadyClaimed);
+                require!(ticket.holds(winner_id), LottryError::NotWinner);
+                pool.claimed_tiers |= 1 << tier;
+                (pool.tier_share(tier)?, winner_id, 1)
+            }
+        };
+        require!(share > 0, LottryError::NoStakedFunds);
 
-        let tax_amount = (total_staked as u128)
-            .checked_mul(pool.tax_rate_bps as u128)
-            .ok_or(LottryError::MathOverflow)?
-            / 10_000u128;
-        require!(tax_amount <= u64::MAX as u128, LottryError::MathOverflow);
-        let tax_u64 = tax_amount as u64;
-        let payout = total_staked
+        // Purchases were already charged `purchase_fee_bps`; this is the
+        // separate, independently configured fee on the payout.
+        let tax_u64 = bps_of(share, pool.payout_fee_bps)?;
+        let payout = share
             .checked_sub(tax_u64)
             .ok_or(LottryError::MathOverflow)?;
 
-        **pool.to_account_info().try_borrow_mut_lamports()? -= payout;
-        **ctx.accounts.winner.to_account_info().try_borrow_mut_lamports()? += payout;
+        pay_out_of_pool(
+            pool,
+            ctx.bumps.lottery_pool,
+            &ctx.accounts.winner.to_account_info(),
+            token.as_mut(),
+            payout,
+        )?;
 
-        pool.total_staked_sol = 0;
+        pool.total_staked_sol = pool
+            .total_staked_sol
+            .checked_sub(share)
+            .ok_or(LottryError::MathOverflow)?;
         pool.tax_treasury_sol = pool
             .tax_treasury_sol
             .checked_add(tax_u64)
             .ok_or(LottryError::MathOverflow)?;
-        ticket.is_active = false;
+        pool.assert_solvent(&pool.to_account_info(), token.as_ref())?;
 
+        emit_cpi!(PrizeClaimed {
+            epoch_id,
+            tier,
+            ticket_id: winner_id,
+            entries,
+            winner: ctx.accounts.winner.key(),
+            payout,
+            tax: tax_u64,
+        });
         msg!(
-            "Prize claimed: winner={} payout={} tax={}",
+            "Prize claimed: winner={} tier={} ticket=#{} payout={} tax={}",
             ctx.accounts.winner.key(),
+            tier,
+            winner_id,
             payout,
             tax_u64
         );
         Ok(())
     }
 
-    /// Withdraw accumulated taxes to the treasury wallet (admin-only).
+    /// Withdraw accumulated taxes (admin-only). The protocol's cut goes to
+    /// the config `fee_recipient`, the rest to the treasury wallet. Once every
+    /// drawn tier is paid out or forfeited, the rounding dust of the tier
+    /// shares goes with the taxes; tiers nobody won stay staked for
+    /// `rollover_jackpot`.
     pub fn withdraw_taxes(ctx: Context<WithdrawTaxes>, epoch_id: u64) -> Result<()> {
         let pool = &mut ctx.accounts.lottery_pool;
         require!(pool.epoch_id == epoch_id, LottryError::EpochMismatch);
@@ -453,142 +1444,2144 @@ pub mod lotry {
             ctx.accounts.authority.key(),
             LottryError::Unauthorized
         );
+        // Taxes stay refundable until the epoch has settled; a cancelled
+        // epoch owes all of them back through `claim_refund`
+        require!(!pool.is_cancelled, LottryError::EpochCancelled);
+        require!(
+            pool.is_drawn() || pool.rolled_over_to.is_some(),
+            LottryError::WinnerNotSet
+        );
+
+        if pool.is_drawn()
+            && Clock::get()?.unix_timestamp >= pool.reveal_deadline
+            && pool.unclaimed_prizes()? == 0
+        {
+            // Pure re-booking: the lamports or tokens stay in the pool
+            let dust = pool.total_staked_sol.saturating_sub(pool.unwon_prizes()?);
+            pool.tax_treasury_sol = pool
+                .tax_treasury_sol
+                .checked_add(dust)
+                .ok_or(LottryError::MathOverflow)?;
+            pool.total_staked_sol = pool
+                .total_staked_sol
+                .checked_sub(dust)
+                .ok_or(LottryError::MathOverflow)?;
+        }
 
         let amount = pool.tax_treasury_sol;
         require!(amount > 0, LottryError::NoTaxes);
+        let protocol_fee = bps_of(amount, pool.protocol_fee_bps)?;
+        let treasury_share = amount
+            .checked_sub(protocol_fee)
+            .ok_or(LottryError::MathOverflow)?;
 
-        **pool.to_account_info().try_borrow_mut_lamports()? -= amount;
-        **ctx.accounts.treasury.to_account_info().try_borrow_mut_lamports()? += amount;
+        if protocol_fee > 0 {
+            let mut token = resolve_token_accounts(
+                &ctx.accounts.lottery_pool,
+                &ctx.accounts.mint,
+                &mut ctx.accounts.pool_vault,
+                &ctx.accounts.fee_recipient_token_account,
+                &ctx.accounts.token_program,
+                &ctx.accounts.fee_recipient.key(),
+            )?;
+            pay_out_of_pool(
+                &ctx.accounts.lottery_pool,
+                ctx.bumps.lottery_pool,
+                &ctx.accounts.fee_recipient.to_account_info(),
+                token.as_mut(),
+                protocol_fee,
+            )?;
+        }
+
+        let mut token = resolve_token_accounts(
+            &ctx.accounts.lottery_pool,
+            &ctx.accounts.mint,
+            &mut ctx.accounts.pool_vault,
+            &ctx.accounts.treasury_token_account,
+            &ctx.accounts.token_program,
+            &ctx.accounts.treasury.key(),
+        )?;
+        let pool = &mut ctx.accounts.lottery_pool;
+        pay_out_of_pool(
+            pool,
+            ctx.bumps.lottery_pool,
+            &ctx.accounts.treasury.to_account_info(),
+            token.as_mut(),
+            treasury_share,
+        )?;
 
         pool.tax_treasury_sol = 0;
+        pool.assert_solvent(&pool.to_account_info(), token.as_ref())?;
+        emit_cpi!(TaxesWithdrawn {
+            epoch_id,
+            treasury: ctx.accounts.treasury.key(),
+            amount: treasury_share,
+            fee_recipient: ctx.accounts.fee_recipient.key(),
+            protocol_fee,
+        });
         msg!(
-            "Taxes withdrawn: treasury={} amount={}",
+            "Taxes withdrawn: treasury={} amount={} protocol_fee={}",
             ctx.accounts.treasury.key(),
-            amount
+            treasury_share,
+            protocol_fee
         );
         Ok(())
     }
-}
 
-// ──────────────────────────────────────────────────────────────────────────────
-// Account Structs
-// ──────────────────────────────────────────────────────────────────────────────
+    // ── Refunds ───────────────────────────────────────────────────────────────
 
-/// Global lottery pool — tracks epoch state.
-#[account]
-pub struct LotteryPool {
-    pub authority: Pubkey,         // 32
-    pub epoch_id: u64,             // 8
-    pub ticket_count: u64,         // 8
-    pub total_staked_sol: u64,     // 8
-    pub tax_treasury_sol: u64,     // 8
-    pub base_price: u64,           // 8
-    pub curve_multiplier: u64,     // 8
-    pub tax_rate_bps: u16,         // 2
-    pub is_active: bool,           // 1
-    pub vrf_request_id: Option<Pubkey>, // 1 + 32
-    pub winner_ticket_id: Option<u64>,  // 1 + 8
-}
+    /// Cancel an undrawn epoch on L1 (admin-only), opening refunds.
+    pub fn cancel_epoch(ctx: Context<CancelEpoch>, epoch_id: u64) -> Result<()> {
+        let pool = &mut ctx.accounts.lottery_pool;
+        require!(pool.epoch_id == epoch_id, LottryError::EpochMismatch);
+        require_keys_eq!(
+            pool.authority,
+            ctx.accounts.authority.key(),
+            LottryError::Unauthorized
+        );
+        require!(!pool.is_cancelled, LottryError::EpochCancelled);
+        require!(!pool.is_drawn(), LottryError::WinnerAlreadySet);
+        require!(pool.rolled_over_to.is_none(), LottryError::JackpotRolledOver);
 
-impl LotteryPool {
-    pub const LEN: usize = 8 + 32 + 8 + 8 + 8 + 8 + 8 + 8 + 2 + 1 + (1 + 32) + (1 + 8);
+        pool.is_cancelled = true;
+        pool.is_active = false;
 
-    pub fn current_price(&self) -> Result<u64> {
-        let price = (self.base_price as u128)
-            .checked_add(
-                (self.curve_multiplier as u128)
-                    .checked_mul(self.total_staked_sol as u128)
-                    .ok_or(LottryError::MathOverflow)?,
-            )
-            .ok_or(LottryError::MathOverflow)?;
-        require!(price <= u64::MAX as u128, LottryError::MathOverflow);
-        Ok(price as u64)
+        emit_cpi!(EpochCancelled {
+            epoch_id,
+            timed_out: false,
+        });
+        msg!("Epoch {} cancelled — refunds open", epoch_id);
+        Ok(())
     }
-}
 
-/// Individual participant ticket — shielded in TEE.
-#[account]
-pub struct PlayerTicket {
-    pub owner: Pubkey,         // 32
-    pub epoch_id: u64,         // 8
-    pub ticket_id: u64,        // 8
-    pub ticket_data: [u8; 32], // 32 (hashed/shielded entry)
-    pub balance: u64,          // 8
-    pub is_active: bool,       // 1
-}
+    /// Refund everything a player paid into a cancelled epoch, including
+    /// unused credits. If the epoch was never drawn, anyone holding a ticket
+    /// may claim once `REFUND_TIMEOUT_SECS` has passed after `draw_at`.
+    pub fn claim_refund(ctx: Context<ClaimRefund>, epoch_id: u64) -> Result<()> {
+        let mut token = resolve_token_accounts(
+            &ctx.accounts.lottery_pool,
+            &ctx.accounts.mint,
+            &mut ctx.accounts.pool_vault,
+            &ctx.accounts.player_token_account,
+            &ctx.accounts.token_program,
+            &ctx.accounts.player.key(),
+        )?;
+        let pool = &mut ctx.accounts.lottery_pool;
+        let ticket = &mut ctx.accounts.player_ticket;
 
-impl PlayerTicket {
-    pub const LEN: usize = 8 + 32 + 8 + 8 + 32 + 8 + 1;
-}
+        require!(pool.epoch_id == epoch_id, LottryError::EpochMismatch);
+        if !pool.is_cancelled {
+            require!(!pool.is_drawn(), LottryError::WinnerAlreadySet);
+            require!(pool.rolled_over_to.is_none(), LottryError::JackpotRolledOver);
+            let refund_opens_at = pool
+                .draw_at
+                .checked_add(REFUND_TIMEOUT_SECS)
+                .ok_or(LottryError::MathOverflow)?;
+            require!(
+                Clock::get()?.unix_timestamp >= refund_opens_at,
+                LottryError::RefundNotAvailable
+            );
+            // The abandoned epoch can no longer be drawn
+            pool.is_cancelled = true;
+            pool.is_active = false;
+            emit_cpi!(EpochCancelled {
+                epoch_id,
+                timed_out: true,
+            });
+        }
 
-/// Session token — secondary signer PDA for frictionless UX.
-#[account]
-pub struct SessionToken {
-    pub authority: Pubkey,     // 32 — primary wallet
-    pub ephemeral_key: Pubkey, // 32 — temp client-side keypair
-    pub valid_until: i64,      // 8  — unix timestamp expiry
-}
+        require_keys_eq!(
+            ticket.owner,
+            ctx.accounts.player.key(),
+            LottryError::InvalidTicketOwner
+        );
+        let refund = ticket.paid_sol;
+        require!(refund > 0, LottryError::NothingToRefund);
+        let net = refund
+            .checked_sub(ticket.paid_tax_sol)
+            .ok_or(LottryError::MathOverflow)?;
 
-impl SessionToken {
-    pub const LEN: usize = 8 + 32 + 32 + 8;
-}
+        pool.total_staked_sol = pool
+            .total_staked_sol
+            .checked_sub(net)
+            .ok_or(LottryError::MathOverflow)?;
+        pool.tax_treasury_sol = pool
+            .tax_treasury_sol
+            .checked_sub(ticket.paid_tax_sol)
+            .ok_or(LottryError::MathOverflow)?;
+        pool.refunded_sol = pool
+            .refunded_sol
+            .checked_add(refund)
+            .ok_or(LottryError::MathOverflow)?;
 
-// ──────────────────────────────────────────────────────────────────────────────
-// Contexts
-// ──────────────────────────────────────────────────────────────────────────────
+        pay_out_of_pool(
+            pool,
+            ctx.bumps.lottery_pool,
+            &ctx.accounts.player.to_account_info(),
+            token.as_mut(),
+            refund,
+        )?;
+        pool.assert_solvent(&pool.to_account_info(), token.as_ref())?;
 
-// ── Phase 1 ──────────────────────────────────────────────────────────────────
+        ticket.paid_sol = 0;
+        ticket.paid_tax_sol = 0;
+        ticket.balance = 0;
+        ticket.is_active = false;
 
-#[derive(Accounts)]
-#[instruction(epoch_id: u64)]
-pub struct InitializeLottery<'info> {
-    #[account(
-        init,
-        payer = authority,
-        space = LotteryPool::LEN,
-        seeds = [LOTTERY_POOL_SEED, &epoch_id.to_le_bytes()],
-        bump
-    )]
-    pub lottery_pool: Account<'info, LotteryPool>,
-    #[account(mut)]
-    pub authority: Signer<'info>,
-    pub system_program: Program<'info, System>,
-}
+        emit_cpi!(RefundClaimed {
+            epoch_id,
+            player: ctx.accounts.player.key(),
+            amount: refund,
+        });
+        msg!(
+            "Refund claimed: player={} epoch={} amount={}",
+            ctx.accounts.player.key(),
+            epoch_id,
+            refund
+        );
+        Ok(())
+    }
 
-// ── Phase 2 ──────────────────────────────────────────────────────────────────
+    // ── Rollover ──────────────────────────────────────────────────────────────
 
-#[derive(Accounts)]
-#[instruction(epoch_id: u64)]
-pub struct DelegateLottery<'info> {
-    #[account(
-        mut,
-        seeds = [LOTTERY_POOL_SEED, &epoch_id.to_le_bytes()],
-        bump,
-    )]
-    /// CHECK: delegating pda
-    pub lottery_pool: Account<'info, LotteryPool>,
-    
-    #[account(mut)]
+    /// Carry stake no winner can claim into a later epoch's prize pool
+    /// (admin-only, on L1). An epoch that sold no credits rolls over its whole
+    /// stake once `draw_at` passes; a drawn epoch rolls over what is left
+    /// after reserving every unclaimed drawn tier; a cancelled epoch rolls
+    /// over the stake carried into it, leaving what players paid to refunds.
+    pub fn rollover_jackpot(
+        ctx: Context<RolloverJackpot>,
+        epoch_id: u64,
+        next_epoch_id: u64,
+    ) -> Result<()> {
+        let next_pool_key = ctx.accounts.next_pool.key();
+        let mut token = resolve_token_accounts(
+            &ctx.accounts.lottery_pool,
+            &ctx.accounts.mint,
+            &mut ctx.accounts.pool_vault,
+            &ctx.accounts.next_pool_vault,
+            &ctx.accounts.token_program,
+            &next_pool_key,
+        )?;
+        let pool = &mut ctx.accounts.lottery_pool;
+        let next_pool = &mut ctx.accounts.next_pool;
+
+        require!(pool.epoch_id == epoch_id, LottryError::EpochMismatch);
+        require_keys_eq!(
+            pool.authority,
+            ctx.accounts.authority.key(),
+            LottryError::Unauthorized
+        );
+        pool.require_rollover_target(next_pool, token.as_ref())?;
+
+        let amount = if pool.is_cancelled {
+            require!(pool.rolled_over_to.is_none(), LottryError::JackpotRolledOver);
+            pool.rolled_in_sol
+        } else {
+            if !pool.is_drawn() {
+                // Any credit sold is owed back through `claim_refund` instead
+                require!(
+                    pool.credits_sold == 0 && Clock::get()?.unix_timestamp >= pool.draw_at,
+                    LottryError::RolloverNotAvailable
+                );
+                // Nothing can be drawn from this epoch any more
+                pool.is_active = false;
+            } else {
+                // Unmatched pick-number tiers are only known once reveals close
+                require!(
+                    Clock::get()?.unix_timestamp >= pool.reveal_deadline,
+                    LottryError::RevealWindowOpen
+                );
+            }
+            pool.total_staked_sol
+                .checked_sub(pool.unclaimed_prizes()?)
+                .ok_or(LottryError::MathOverflow)?
+        };
+        require!(amount > 0, LottryError::NoStakedFunds);
+
+        roll_into(
+            pool,
+            ctx.bumps.lottery_pool,
+            next_pool,
+            token.as_mut(),
+            amount,
+        )?;
+
+        emit_cpi!(JackpotRolledOver {
+            epoch_id,
+            next_epoch_id,
+            amount,
+        });
+        msg!(
+            "Jackpot rolled over: epoch {} -> epoch {} amount={}",
+            epoch_id,
+            next_epoch_id,
+            amount
+        );
+        Ok(())
+    }
+
+    /// Forfeit every drawn tier still unclaimed at `claim_deadline` (admin-only).
+    /// Per the pool's `forfeit_policy` the prizes move to the fee treasury or
+    /// into `next_pool`'s prize pool.
+    pub fn sweep_unclaimed(ctx: Context<SweepUnclaimed>, epoch_id: u64) -> Result<()> {
+        let policy = ctx.accounts.lottery_pool.forfeit_policy;
+        let mut token = match (policy, ctx.accounts.next_pool.as_ref()) {
+            (ForfeitPolicy::Treasury, _) => None,
+            (ForfeitPolicy::NextEpoch, Some(next_pool)) => resolve_token_accounts(
+                &ctx.accounts.lottery_pool,
+                &ctx.accounts.mint,
+                &mut ctx.accounts.pool_vault,
+                &ctx.accounts.next_pool_vault,
+                &ctx.accounts.token_program,
+                &next_pool.key(),
+            )?,
+            (ForfeitPolicy::NextEpoch, None) => return err!(LottryError::InvalidRolloverTarget),
+        };
+        let pool = &mut ctx.accounts.lottery_pool;
+
+        require!(pool.epoch_id == epoch_id, LottryError::EpochMismatch);
+        require_keys_eq!(
+            pool.authority,
+            ctx.accounts.authority.key(),
+            LottryError::Unauthorized
+        );
+        require!(pool.is_drawn(), LottryError::WinnerNotSet);
+        require!(
+            Clock::get()?.unix_timestamp >= pool.claim_deadline,
+            LottryError::ClaimWindowOpen
+        );
+
+        let forfeited = pool.unclaimed_prizes()?;
+        require!(forfeited > 0, LottryError::NothingToSweep);
+        let next_epoch_id = ctx.accounts.next_pool.as_ref().map(|next| next.epoch_id);
+        // Every drawn tier is now settled, paid or not
+        pool.settle_tiers();
+
+        match policy {
+            ForfeitPolicy::Treasury => {
+                // Pure re-booking: the lamports or tokens stay in the pool
+                pool.total_staked_sol = pool
+                    .total_staked_sol
+                    .checked_sub(forfeited)
+                    .ok_or(LottryError::MathOverflow)?;
+                pool.tax_treasury_sol = pool
+                    .tax_treasury_sol
+                    .checked_add(forfeited)
+                    .ok_or(LottryError::MathOverflow)?;
+            }
+            ForfeitPolicy::NextEpoch => {
+                let next_pool = ctx
+                    .accounts
+                    .next_pool
+                    .as_mut()
+                    .ok_or(LottryError::InvalidRolloverTarget)?;
+                pool.require_rollover_target(next_pool, token.as_ref())?;
+                roll_into(
+                    pool,
+                    ctx.bumps.lottery_pool,
+                    next_pool,
+                    token.as_mut(),
+                    forfeited,
+                )?;
+            }
+        }
+
+        emit_cpi!(UnclaimedSwept {
+            epoch_id,
+            amount: forfeited,
+            policy,
+            next_epoch_id: match policy {
+                ForfeitPolicy::Treasury => None,
+                ForfeitPolicy::NextEpoch => next_epoch_id,
+            },
+        });
+        msg!(
+            "Unclaimed prizes swept: epoch {} amount={} policy={:?}",
+            epoch_id,
+            forfeited,
+            policy
+        );
+        Ok(())
+    }
+
+    // ── Cleanup ───────────────────────────────────────────────────────────────
+
+    /// Close a PlayerTicket on L1 once it has nothing left to collect, and
+    /// return its rent to the owner. Signed by the owner, or by the pool
+    /// authority clearing out tickets before `close_lottery_pool`.
+    pub fn close_player_ticket(ctx: Context<ClosePlayerTicket>, epoch_id: u64) -> Result<()> {
+        let pool = &mut ctx.accounts.lottery_pool;
+        let ticket = &ctx.accounts.player_ticket;
+        let closer = ctx.accounts.closer.key();
+
+        require!(pool.epoch_id == epoch_id, LottryError::EpochMismatch);
+        require!(
+            closer == ticket.owner || closer == pool.authority,
+            LottryError::Unauthorized
+        );
+        require!(
+            pool.is_ticket_settled(ticket, Clock::get()?.unix_timestamp),
+            LottryError::TicketNotSettled
+        );
+
+        pool.open_tickets = pool
+            .open_tickets
+            .checked_sub(1)
+            .ok_or(LottryError::MathOverflow)?;
+
+        emit_cpi!(PlayerTicketClosed {
+            epoch_id,
+            owner: ticket.owner,
+        });
+        msg!(
+            "PlayerTicket of {} for epoch {} closed",
+            ticket.owner,
+            epoch_id
+        );
+        Ok(())
+    }
+
+    /// Close a settled LotteryPool (admin-only) and return its rent, plus
+    /// the token vault's, to whoever paid for the pool. Every prize, refund
+    /// and fee must be paid out and every PlayerTicket closed. A weighted
+    /// epoch's tree must be passed and is closed to the authority that
+    /// created it, so its rent and address are freed with the pool (an epoch
+    /// that never sold an entry can still create one with `init_weight_tree`).
+    pub fn close_lottery_pool(ctx: Context<CloseLotteryPool>, epoch_id: u64) -> Result<()> {
+        let pool = &ctx.accounts.lottery_pool;
+        require!(pool.epoch_id == epoch_id, LottryError::EpochMismatch);
+        require_keys_eq!(
+            pool.authority,
+            ctx.accounts.authority.key(),
+            LottryError::Unauthorized
+        );
+        if pool.game_mode == GameMode::Weighted {
+            require!(
+                ctx.accounts.weight_tree.is_some(),
+                LottryError::MissingWeightTree
+            );
+        }
+        require!(
+            pool.is_drawn() || pool.is_cancelled || pool.rolled_over_to.is_some(),
+            LottryError::PoolNotSettled
+        );
+        require!(
+            pool.total_staked_sol == 0 && pool.tax_treasury_sol == 0,
+            LottryError::PoolNotSettled
+        );
+        require!(pool.open_tickets == 0, LottryError::TicketsStillOpen);
+
+        if let Some(token_vault) = pool.token_vault {
+            let (Some(vault), Some(token_program)) = (
+                ctx.accounts.pool_vault.as_ref(),
+                ctx.accounts.token_program.as_ref(),
+            ) else {
+                return err!(LottryError::MissingTokenAccounts);
+            };
+            require_keys_eq!(vault.key(), token_vault, LottryError::InvalidTokenAccount);
+
+            let epoch_bytes = epoch_id.to_le_bytes();
+            let signer_seeds: &[&[&[u8]]] = &[&[
+                LOTTERY_POOL_SEED,
+                &epoch_bytes,
+                &[ctx.bumps.lottery_pool],
+            ]];
+            close_account(CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                CloseAccount {
+                    account: vault.to_account_info(),
+                    destination: ctx.accounts.rent_payer.to_account_info(),
+                    authority: pool.to_account_info(),
+                },
+                signer_seeds,
+            ))?;
+        }
+
+        emit_cpi!(LotteryPoolClosed {
+            epoch_id,
+            rent_payer: ctx.accounts.rent_payer.key(),
+        });
+        msg!(
+            "LotteryPool epoch {} closed, rent returned to {}",
+            epoch_id,
+            ctx.accounts.rent_payer.key()
+        );
+        Ok(())
+    }
+}
+
+// ──────────────────────────────────────────────────────────────────────────────
+// Account Structs
+// ──────────────────────────────────────────────────────────────────────────────
+
+/// Program-wide settings and the allowlists every lottery is checked against.
+#[account]
+pub struct ProgramConfig {
+    pub admin: Pubkey,                   // 32
+    pub protocol_fee_bps: u16,           // 2 — cut of each pool's taxes
+    pub fee_recipient: Pubkey,           // 32
+    pub min_base_price: u64,             // 8
+    pub max_base_price: u64,             // 8
+    pub allowed_validators: Vec<Pubkey>, // 4 + MAX_ALLOWED_VALIDATORS * 32
+    pub lottery_creators: Vec<Pubkey>,   // 4 + MAX_LOTTERY_CREATORS * 32 — besides the admin
+    pub max_session_lifetime_secs: i64,  // 8
+}
+
+impl ProgramConfig {
+    pub const LEN: usize = 8 + 32 + 2 + 32 + 8 + 8
+        + (4 + MAX_ALLOWED_VALIDATORS * 32) + (4 + MAX_LOTTERY_CREATORS * 32) + 8;
+
+    pub fn updated_event(&self) -> ConfigUpdated {
+        ConfigUpdated {
+            admin: self.admin,
+            protocol_fee_bps: self.protocol_fee_bps,
+            fee_recipient: self.fee_recipient,
+            min_base_price: self.min_base_price,
+            max_base_price: self.max_base_price,
+            max_session_lifetime_secs: self.max_session_lifetime_secs,
+        }
+    }
+
+    pub fn apply(&mut self, params: &ConfigParams) {
+        self.protocol_fee_bps = params.protocol_fee_bps;
+        self.fee_recipient = params.fee_recipient;
+        self.min_base_price = params.min_base_price;
+        self.max_base_price = params.max_base_price;
+        self.allowed_validators = params.allowed_validators.clone();
+        self.lottery_creators = params.lottery_creators.clone();
+        self.max_session_lifetime_secs = params.max_session_lifetime_secs;
+    }
+
+    /// The validator a delegation targets; it must be allowlisted, or be
+    /// `TEE_VALIDATOR` while the allowlist is empty.
+    pub fn require_allowed_validator(&self, validator: Option<&AccountInfo>) -> Result<Pubkey> {
+        let key = validator
+            .map(|v| *v.key)
+            .ok_or(LottryError::InvalidValidator)?;
+        let allowed = if self.allowed_validators.is_empty() {
+            key == TEE_VALIDATOR
+        } else {
+            self.allowed_validators.contains(&key)
+        };
+        require!(allowed, LottryError::InvalidValidator);
+        Ok(key)
+    }
+
+    /// Whether `key` may create lotteries.
+    pub fn is_creator(&self, key: &Pubkey) -> bool {
+        *key == self.admin || self.lottery_creators.contains(key)
+    }
+
+    pub fn require_price_in_bounds(&self, base_price: u64) -> Result<()> {
+        require!(
+            base_price >= self.min_base_price && base_price <= self.max_base_price,
+            LottryError::PriceOutOfBounds
+        );
+        Ok(())
+    }
+
+    /// Sessions may not be valid for longer than `max_session_lifetime_secs`
+    /// from now.
+    pub fn require_session_lifetime(&self, valid_until: i64, now: i64) -> Result<()> {
+        require!(
+            valid_until.saturating_sub(now) <= self.max_session_lifetime_secs,
+            LottryError::SessionLifetimeExceeded
+        );
+        Ok(())
+    }
+}
+
+/// Settings supplied to `init_config` / `update_config`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ConfigParams {
+    pub protocol_fee_bps: u16,
+    pub fee_recipient: Pubkey,
+    pub min_base_price: u64,
+    pub max_base_price: u64,
+    /// ER validators pools may be delegated to
+    pub allowed_validators: Vec<Pubkey>,
+    /// Wallets other than the admin allowed to call `initialize_lottery`
+    pub lottery_creators: Vec<Pubkey>,
+    /// Cap on how far ahead `issue_session` / `renew_session` may set expiry
+    pub max_session_lifetime_secs: i64,
+}
+
+impl ConfigParams {
+    pub fn validate(&self) -> Result<()> {
+        require!(self.protocol_fee_bps <= 10_000, LottryError::InvalidTaxRate);
+        require!(
+            self.min_base_price <= self.max_base_price,
+            LottryError::InvalidPriceBounds
+        );
+        require!(
+            self.allowed_validators.len() <= MAX_ALLOWED_VALIDATORS
+                && self.lottery_creators.len() <= MAX_LOTTERY_CREATORS,
+            LottryError::ConfigListTooLong
+        );
+        require!(
+            self.max_session_lifetime_secs > 0,
+            LottryError::InvalidSessionLifetime
+        );
+        Ok(())
+    }
+}
+
+/// Global lottery pool — tracks epoch state.
+/// `*_sol` amounts are lamports, or mint base units when `mint` is set.
+#[account]
+pub struct LotteryPool {
+    pub authority: Pubkey,         // 32
+    pub draw_authority: Pubkey,    // 32 — crank allowed to draw before `draw_grace_secs` end
+    pub epoch_id: u64,             // 8
+    pub ticket_count: u64,         // 8
+    pub total_staked_sol: u64,     // 8
+    pub tax_treasury_sol: u64,     // 8
+    pub base_price: u64,           // 8
+    pub pricing_curve: PricingCurve, // PricingCurve::LEN
+    pub credits_sold: u64,         // 8 — position on the pricing curve
+    pub purchase_fee_bps: u16,     // 2 — skimmed from every credit purchase
+    pub payout_fee_bps: u16,       // 2 — skimmed from the prize on claim
+    pub is_active: bool,           // 1
+    pub sale_opens_at: i64,        // 8
+    pub sale_closes_at: i64,       // 8
+    pub draw_at: i64,              // 8 — earliest draw time
+    pub is_cancelled: bool,        // 1
+    pub refunded_sol: u64,         // 8
+    pub mint: Option<Pubkey>,      // 1 + 32 — None for native SOL pools
+    pub token_vault: Option<Pubkey>, // 1 + 32
+    pub prize_tiers: Vec<u16>,     // 4 + MAX_PRIZE_TIERS * 2 — bps of the prize pool per tier
+    pub prize_pool_sol: u64,       // 8 — total_staked_sol snapshot at the draw
+    pub vrf_request_id: Option<Pubkey>, // 1 + 32
+    pub winning_ticket_ids: Vec<u64>,   // 4 + MAX_PRIZE_TIERS * 8 — one per drawn tier
+    pub claimed_tiers: u8,              // 1 — bitmask of paid-out or forfeited tiers
+    pub rolled_over_to: Option<u64>,    // 1 + 8 — epoch that received the leftover stake
+    pub rolled_in_sol: u64,             // 8 — stake carried in from earlier epochs
+    pub claim_window_secs: i64,         // 8
+    pub forfeit_policy: ForfeitPolicy,  // 1
+    pub claim_deadline: i64,            // 8 — set at the draw; later claims are rejected
+    pub protocol_fee_bps: u16,          // 2 — protocol cut of the taxes, fixed at creation
+    pub validator: Option<Pubkey>,      // 1 + 32 — ER validator the pool is delegated to
+    pub rent_payer: Pubkey,             // 32 — refunded when the pool is closed
+    pub open_tickets: u64,              // 8 — PlayerTickets not yet closed
+    pub game_mode: GameMode,            // GameMode::LEN
+    pub reveal_window_secs: i64,        // 8
+    pub reveal_deadline: i64,           // 8 — set at the draw; claims open after it
+    pub winning_numbers: Vec<u32>,      // 4 + MAX_PRIZE_TIERS * 4 — one per tier, or the Pick-N combination
+    pub tier_winners: Vec<u32>,         // 4 + MAX_PRIZE_TIERS * 4 — registered Pick-N winners per tier
+    pub tier_claims: Vec<u32>,          // 4 + MAX_PRIZE_TIERS * 4 — of which paid out or forfeited
+    pub draw_grace_secs: i64,           // 8 — authority-only draw period after draw_at
+}
+
+impl LotteryPool {
+    pub const LEN: usize =
+        8 + 32 + 32 + 8 + 8 + 8 + 8 + 8 + PricingCurve::LEN + 8 + 2 + 2 + 1 + 8 + 8 + 8 + 1 + 8 + (1 + 32) + (1 + 32)
+            + (4 + MAX_PRIZE_TIERS * 2) + 8 + (1 + 32) + (4 + MAX_PRIZE_TIERS * 8) + 1 + (1 + 8) + 8
+            + 8 + 1 + 8 + 2 + (1 + 32) + 32 + 8
+            + GameMode::LEN + 8 + 8 + (4 + MAX_PRIZE_TIERS * 4) + (4 + MAX_PRIZE_TIERS * 4)
+            + (4 + MAX_PRIZE_TIERS * 4) + 8;
+
+    /// Reset every field for a freshly created epoch.
+    pub fn init(
+        &mut self,
+        authority: Pubkey,
+        rent_payer: Pubkey,
+        epoch_id: u64,
+        params: &LotteryParams,
+        protocol_fee_bps: u16,
+    ) {
+        self.authority = authority;
+        self.draw_authority = params.draw_authority;
+        self.epoch_id = epoch_id;
+        self.ticket_count = 0;
+        self.total_staked_sol = 0;
+        self.tax_treasury_sol = 0;
+        self.base_price = params.base_price;
+        self.pricing_curve = params.pricing_curve;
+        self.credits_sold = 0;
+        self.purchase_fee_bps = params.purchase_fee_bps;
+        self.payout_fee_bps = params.payout_fee_bps;
+        self.is_active = true;
+        self.sale_opens_at = params.sale_opens_at;
+        self.sale_closes_at = params.sale_closes_at;
+        self.draw_at = params.draw_at;
+        self.is_cancelled = false;
+        self.refunded_sol = 0;
+        self.mint = None;
+        self.token_vault = None;
+        self.prize_tiers = params.prize_tiers.clone();
+        self.prize_pool_sol = 0;
+        self.vrf_request_id = None;
+        self.winning_ticket_ids = Vec::new();
+        self.claimed_tiers = 0;
+        self.rolled_over_to = None;
+        self.rolled_in_sol = 0;
+        self.claim_window_secs = params.claim_window_secs;
+        self.forfeit_policy = params.forfeit_policy;
+        self.claim_deadline = 0;
+        self.protocol_fee_bps = protocol_fee_bps;
+        self.validator = None;
+        self.rent_payer = rent_payer;
+        self.open_tickets = 0;
+        self.game_mode = params.game_mode;
+        self.reveal_window_secs = params.reveal_window_secs;
+        self.reveal_deadline = 0;
+        self.winning_numbers = Vec::new();
+        self.tier_winners = Vec::new();
+        self.tier_claims = Vec::new();
+        self.draw_grace_secs = params.draw_grace_secs;
+    }
+
+    /// Event describing the freshly initialized pool at address `pool`.
+    pub fn initialized_event(&self, pool: Pubkey, series_id: Option<u32>) -> LotteryInitialized {
+        LotteryInitialized {
+            epoch_id: self.epoch_id,
+            pool,
+            authority: self.authority,
+            series_id,
+            base_price: self.base_price,
+            pricing_curve: self.pricing_curve,
+            draw_authority: self.draw_authority,
+            sale_opens_at: self.sale_opens_at,
+            sale_closes_at: self.sale_closes_at,
+            draw_at: self.draw_at,
+            prize_tiers: self.prize_tiers.clone(),
+            game_mode: self.game_mode,
+            draw_grace_secs: self.draw_grace_secs,
+        }
+    }
+
+    /// Lamports the pool owes out: prize pool plus fee treasury.
+    pub fn tracked_lamports(&self) -> Result<u64> {
+        self.total_staked_sol
+            .checked_add(self.tax_treasury_sol)
+            .ok_or(LottryError::MathOverflow.into())
+    }
+
+    /// Accounting invariant: `total_staked_sol + tax_treasury_sol` equals the
+    /// lamports held above rent (or the vault balance for token pools). Direct
+    /// transfers in can only push the balance above that, so a shortfall is
+    /// what gets rejected.
+    pub fn assert_solvent(
+        &self,
+        info: &AccountInfo,
+        token: Option<&PoolTokenAccounts>,
+    ) -> Result<()> {
+        self.assert_holds(info, token.map(|token| token.vault.amount))
+    }
+
+    /// `assert_solvent` against a vault balance read by the caller, for a
+    /// pool whose vault is the other side of the transfer.
+    pub fn assert_holds(&self, info: &AccountInfo, vault_amount: Option<u64>) -> Result<()> {
+        let held = match vault_amount {
+            Some(amount) => amount,
+            None => {
+                let rent = Rent::get()?.minimum_balance(info.data_len());
+                info.lamports().saturating_sub(rent)
+            }
+        };
+        require!(
+            held >= self.tracked_lamports()?,
+            LottryError::AccountingMismatch
+        );
+        Ok(())
+    }
+
+    /// Ticket sales run over `[sale_opens_at, sale_closes_at)`.
+    pub fn require_sale_open(&self, now: i64) -> Result<()> {
+        require!(now >= self.sale_opens_at, LottryError::SaleNotOpen);
+        require!(now < self.sale_closes_at, LottryError::SaleClosed);
+        Ok(())
+    }
+
+    /// Whether `key` may trigger the draw during the `draw_grace_secs` grace period.
+    pub fn is_draw_authority(&self, key: &Pubkey) -> bool {
+        *key == self.authority || *key == self.draw_authority
+    }
+
+    /// Price of the next credit on the curve.
+    pub fn current_price(&self) -> Result<u64> {
+        self.quote_credits(1)
+    }
+
+    /// Cost of the next `amount` credits: the sum of each credit's price at
+    /// its position on the curve.
+    pub fn quote_credits(&self, amount: u64) -> Result<u64> {
+        let end = self
+            .credits_sold
+            .checked_add(amount)
+            .ok_or(LottryError::MathOverflow)?;
+        let cost = self
+            .pricing_curve
+            .cumulative_cost(self.base_price, end)?
+            .checked_sub(
+                self.pricing_curve
+                    .cumulative_cost(self.base_price, self.credits_sold)?,
+            )
+            .ok_or(LottryError::MathOverflow)?;
+        require!(cost <= u64::MAX as u128, LottryError::MathOverflow);
+        Ok(cost as u64)
+    }
+
+    /// Whether the VRF callback has picked the winning tickets.
+    pub fn is_drawn(&self) -> bool {
+        !self.winning_ticket_ids.is_empty() || !self.winning_numbers.is_empty()
+    }
+
+    pub fn is_tier_claimed(&self, tier: u8) -> bool {
+        self.claimed_tiers & (1 << tier) != 0
+    }
+
+    /// Gross prize for `tier`, before the payout fee.
+    pub fn tier_share(&self, tier: u8) -> Result<u64> {
+        let bps = *self
+            .prize_tiers
+            .get(tier as usize)
+            .ok_or(LottryError::InvalidPrizeTier)?;
+        bps_of(self.prize_pool_sol, bps)
+    }
+
+    /// Gross prize of a single Pick-N winner of `tier`: the tier's share
+    /// split evenly among its registered winners.
+    pub fn winner_share(&self, tier: u8) -> Result<u64> {
+        let winners = *self
+            .tier_winners
+            .get(tier as usize)
+            .ok_or(LottryError::InvalidPrizeTier)?;
+        require!(winners > 0, LottryError::NotWinner);
+        Ok(self.tier_share(tier)? / winners as u64)
+    }
+
+    /// Prize money still owed to drawn tiers that have a winner and have not
+    /// been claimed.
+    pub fn unclaimed_prizes(&self) -> Result<u64> {
+        let mut total: u64 = 0;
+        if let GameMode::PickN { .. } = self.game_mode {
+            for (tier, (winners, claims)) in
+                self.tier_winners.iter().zip(&self.tier_claims).enumerate()
+            {
+                let open = winners
+                    .checked_sub(*claims)
+                    .ok_or(LottryError::MathOverflow)?;
+                if open > 0 {
+                    let owed = self
+                        .winner_share(tier as u8)?
+                        .checked_mul(open as u64)
+                        .ok_or(LottryError::MathOverflow)?;
+                    total = total.checked_add(owed).ok_or(LottryError::MathOverflow)?;
+                }
+            }
+            return Ok(total);
+        }
+        for (tier, id) in self.winning_ticket_ids.iter().enumerate() {
+            let tier = tier as u8;
+            if *id != NO_WINNER && !self.is_tier_claimed(tier) {
+                total = total
+                    .checked_add(self.tier_share(tier)?)
+                    .ok_or(LottryError::MathOverflow)?;
+            }
+        }
+        Ok(total)
+    }
+
+    /// Prize money of the tiers nobody won: undrawn tiers, pick-number tiers
+    /// no entry revealed and Pick-N tiers no entry matched.
+    pub fn unwon_prizes(&self) -> Result<u64> {
+        let mut total: u64 = 0;
+        for tier in 0..self.prize_tiers.len() {
+            let won = match self.game_mode {
+                GameMode::PickN { .. } => self.tier_winners.get(tier).is_some_and(|w| *w > 0),
+                GameMode::RandomTicket | GameMode::PickNumber { .. } | GameMode::Weighted => self
+                    .winning_ticket_ids
+                    .get(tier)
+                    .is_some_and(|id| *id != NO_WINNER),
+            };
+            if !won {
+                total = total
+                    .checked_add(self.tier_share(tier as u8)?)
+                    .ok_or(LottryError::MathOverflow)?;
+            }
+        }
+        Ok(total)
+    }
+
+    /// Whether `ticket` has nothing left to collect from this epoch: refunded
+    /// if cancelled, every tier it won claimed or expired if drawn, or the
+    /// epoch rolled over without a draw.
+    pub fn is_ticket_settled(&self, ticket: &PlayerTicket, now: i64) -> bool {
+        if self.is_cancelled {
+            return ticket.paid_sol == 0;
+        }
+        if self.is_drawn() {
+            if now >= self.claim_deadline {
+                return true;
+            }
+            if now < self.reveal_deadline {
+                return false;
+            }
+            return match self.game_mode {
+                GameMode::PickN { picks, .. } => (0..self.tier_winners.len() as u8).all(|tier| {
+                    ticket.is_tier_claimed(tier)
+                        || !ticket.entries.iter().any(|e| e.matches == Some(picks - tier))
+                }),
+                GameMode::RandomTicket | GameMode::PickNumber { .. } | GameMode::Weighted => self
+                    .winning_ticket_ids
+                    .iter()
+                    .enumerate()
+                    .all(|(tier, id)| !ticket.holds(*id) || self.is_tier_claimed(tier as u8)),
+            };
+        }
+        self.rolled_over_to.is_some()
+    }
+
+    /// Check that `next` may receive this epoch's leftover stake: a later,
+    /// still undrawn epoch of the same operator and denomination.
+    pub fn require_rollover_target(
+        &self,
+        next: &LotteryPool,
+        token: Option<&PoolTokenAccounts>,
+    ) -> Result<()> {
+        require!(
+            next.epoch_id > self.epoch_id
+                && next.authority == self.authority
+                && next.mint == self.mint
+                && !next.is_cancelled
+                && next.vrf_request_id.is_none(),
+            LottryError::InvalidRolloverTarget
+        );
+        if let Some(token) = token {
+            require!(
+                next.token_vault == Some(token.user.key()),
+                LottryError::InvalidTokenAccount
+            );
+        }
+        Ok(())
+    }
+
+    /// Derive one distinct winning ticket per prize tier from a single VRF
+    /// output. With fewer tickets than tiers, the lowest tiers go undrawn.
+    pub fn draw_winners(&self, randomness: &[u8; 32]) -> Result<Vec<u64>> {
+        require!(self.ticket_count > 0, LottryError::NoTickets);
+        let winners = self.prize_tiers.len().min(self.ticket_count as usize);
+        Ok(draw_distinct(randomness, winners, self.ticket_count))
+    }
+
+
+    /// Count how many of a Pick-N entry's numbers were drawn and, if that
+    /// wins a tier, add the entry to the tier's winners.
+    pub fn register_entry(&mut self, ticket_data: &[u8; 32], picks: u8) -> Result<u8> {
+        let matches = ticket_data[..picks as usize]
+            .iter()
+            .filter(|n| self.winning_numbers.contains(&(**n as u32)))
+            .count() as u8;
+        let tier = (picks - matches) as usize;
+        if let Some(winners) = self.tier_winners.get_mut(tier) {
+            *winners = winners.checked_add(1).ok_or(LottryError::MathOverflow)?;
+        }
+        Ok(matches)
+    }
+
+    /// Book `ticket`'s claim of Pick-N `tier`: each of its registered entries
+    /// with the tier's match count is owed one winner's share. Returns the
+    /// gross share, the first winning ticket id and the number of entries.
+    pub fn claim_pick_n_tier(
+        &mut self,
+        ticket: &mut PlayerTicket,
+        tier: u8,
+        picks: u8,
+    ) -> Result<(u64, u64, u32)> {
+        // Bounds-check `tier` before it is used as a bit index
+        let matches = picks
+            .checked_sub(tier)
+            .ok_or(LottryError::InvalidPrizeTier)?;
+        let winner_share = self.winner_share(tier)?;
+        require!(!ticket.is_tier_claimed(tier), LottryError::PrizeAlreadyClaimed);
+        let winning: Vec<u64> = ticket
+            .entries
+            .iter()
+            .filter(|e| e.matches == Some(matches))
+            .map(|e| e.ticket_id)
+            .collect();
+        let first = *winning.first().ok_or(LottryError::NotWinner)?;
+        let share = winner_share
+            .checked_mul(winning.len() as u64)
+            .ok_or(LottryError::MathOverflow)?;
+        ticket.claimed_tiers |= 1 << tier;
+        self.tier_claims[tier as usize] = self.tier_claims[tier as usize]
+            .checked_add(winning.len() as u32)
+            .ok_or(LottryError::MathOverflow)?;
+        Ok((share, first, winning.len() as u32))
+    }
+
+    /// Mark every drawn tier as settled, paid or not.
+    pub fn settle_tiers(&mut self) {
+        self.claimed_tiers |= ((1u16 << self.winning_ticket_ids.len()) - 1) as u8;
+        self.tier_claims = self.tier_winners.clone();
+    }
+
+    /// Make the revealed `ticket_id` the winner of any tier drawn as
+    /// `number`, unless a lower ticket id already matched it.
+    pub fn record_reveal(&mut self, ticket_id: u64, number: u32) {
+        for (tier, drawn) in self.winning_numbers.iter().enumerate() {
+            if *drawn == number && ticket_id < self.winning_ticket_ids[tier] {
+                self.winning_ticket_ids[tier] = ticket_id;
+            }
+        }
+    }
+}
+
+/// `count` distinct numbers in `1..=max_number` from one VRF output.
+fn draw_numbers(randomness: &[u8; 32], count: usize, max_number: u32) -> Vec<u32> {
+    draw_distinct(randomness, count, max_number as u64)
+        .into_iter()
+        .map(|n| n as u32 + 1)
+        .collect()
+}
+
+/// `count` distinct values in `0..range` derived from one VRF output, one
+/// per prize tier or pick. Callers ensure `count <= range`.
+fn draw_distinct(randomness: &[u8; 32], count: usize, range: u64) -> Vec<u64> {
+    let mut values: Vec<u64> = Vec::with_capacity(count);
+    for tier in 0..count as u8 {
+        let digest = hashv(&[randomness, &[tier]]).to_bytes();
+        let mut value = [0u8; 16];
+        value.copy_from_slice(&digest[..16]);
+        let mut drawn = (u128::from_le_bytes(value) % range as u128) as u64;
+        // Walk forward past values already drawn for a higher tier
+        while values.contains(&drawn) {
+            drawn = (drawn + 1) % range;
+        }
+        values.push(drawn);
+    }
+    values
+}
+
+/// Epoch configuration supplied to `initialize_lottery`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct LotteryParams {
+    pub base_price: u64,
+    pub pricing_curve: PricingCurve,
+    pub purchase_fee_bps: u16,
+    pub payout_fee_bps: u16,
+    /// Crank allowed to draw before `draw_grace_secs` have passed after `draw_at`
+    pub draw_authority: Pubkey,
+    pub sale_opens_at: i64,
+    pub sale_closes_at: i64,
+    pub draw_at: i64,
+    /// Share of the prize pool per tier in bps, highest tier first; sums to 10_000
+    pub prize_tiers: Vec<u16>,
+    /// How long winners have to claim after the reveal window
+    pub claim_window_secs: i64,
+    pub forfeit_policy: ForfeitPolicy,
+    pub game_mode: GameMode,
+    /// How long pick-number entries can be revealed after the draw; 0 otherwise
+    pub reveal_window_secs: i64,
+    /// How long after `draw_at` only the authority or crank may draw; ends
+    /// before the epoch becomes refundable
+    pub draw_grace_secs: i64,
+}
+
+impl LotteryParams {
+    pub fn validate(&self) -> Result<()> {
+        self.pricing_curve.validate()?;
+        require!(self.purchase_fee_bps <= 10_000, LottryError::InvalidTaxRate);
+        require!(self.payout_fee_bps <= 10_000, LottryError::InvalidTaxRate);
+        require!(
+            self.sale_opens_at < self.sale_closes_at && self.sale_closes_at <= self.draw_at,
+            LottryError::InvalidSaleWindow
+        );
+        require!(
+            !self.prize_tiers.is_empty()
+                && self.prize_tiers.len() <= MAX_PRIZE_TIERS
+                && self.prize_tiers.iter().all(|bps| *bps > 0)
+                && self.prize_tiers.iter().map(|bps| *bps as u32).sum::<u32>() == 10_000,
+            LottryError::InvalidPrizeTable
+        );
+        require!(self.claim_window_secs > 0, LottryError::InvalidClaimWindow);
+        require!(
+            (0..REFUND_TIMEOUT_SECS).contains(&self.draw_grace_secs),
+            LottryError::InvalidDrawGrace
+        );
+        match self.game_mode {
+            GameMode::RandomTicket | GameMode::Weighted => {
+                require!(self.reveal_window_secs == 0, LottryError::InvalidGameMode)
+            }
+            // Every tier needs a distinct number
+            GameMode::PickNumber { max_number } => require!(
+                max_number as usize >= self.prize_tiers.len() && self.reveal_window_secs > 0,
+                LottryError::InvalidGameMode
+            ),
+            // Tier `t` pays `picks - t` matches, so there is no zero-match tier
+            GameMode::PickN { picks, max_number } => require!(
+                picks > 0
+                    && picks as usize <= MAX_PICKS
+                    && picks <= max_number
+                    && self.prize_tiers.len() <= picks as usize
+                    && self.reveal_window_secs > 0,
+                LottryError::InvalidGameMode
+            ),
+        }
+        Ok(())
+    }
+}
+
+/// How an epoch's winners are determined.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameMode {
+    /// One random ticket id per tier; `ticket_data` is opaque
+    RandomTicket,
+    /// Players commit to a number in `1..=max_number` and reveal it after
+    /// the draw; each tier draws a number
+    PickNumber { max_number: u32 },
+    /// Players pick `picks` distinct numbers in `1..=max_number` (e.g. 6 of
+    /// 49), stored in the first `picks` bytes of `ticket_data` and zero
+    /// after. Tier `t` pays entries matching `picks - t` drawn numbers,
+    /// split evenly among them.
+    PickN { picks: u8, max_number: u8 },
+    /// One random ticket per tier, with odds proportional to the credits
+    /// each entry committed; drawn from the epoch's `WeightTree`
+    Weighted,
+}
+
+impl GameMode {
+    pub const LEN: usize = 1 + 4; // tag + largest variant
+
+    /// Whether one entry can spend several credits; pick-number and Pick-N
+    /// entries carry one pick each.
+    pub fn spans_credits(&self) -> bool {
+        matches!(self, GameMode::RandomTicket | GameMode::Weighted)
+    }
+
+    /// Check a new entry's `ticket_data` against the mode's encoding.
+    pub fn require_valid_ticket_data(&self, ticket_data: &[u8; 32]) -> Result<()> {
+        if let GameMode::PickN { picks, max_number } = *self {
+            let (numbers, padding) = ticket_data.split_at(picks as usize);
+            require!(
+                numbers
+                    .iter()
+                    .enumerate()
+                    .all(|(i, n)| (1..=max_number).contains(n) && !numbers[..i].contains(n))
+                    && padding.iter().all(|b| *b == 0),
+                LottryError::InvalidTicketData
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Where prizes still unclaimed at `claim_deadline` go.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ForfeitPolicy {
+    /// Booked to `tax_treasury_sol`
+    Treasury,
+    /// Carried into a later epoch's prize pool
+    NextEpoch,
+}
+
+/// Sequence of epochs created from one template by `open_next_epoch`.
+#[account]
+pub struct LotterySeries {
+    pub authority: Pubkey,      // 32 — authority of every epoch it opens
+    pub series_id: u32,         // 4
+    pub last_epoch_id: u64,     // 8 — `series_id << 32` before the first epoch
+    pub template: SeriesParams, // SeriesParams::LEN
+}
+
+impl LotterySeries {
+    pub const LEN: usize = 8 + 32 + 4 + 8 + SeriesParams::LEN;
+
+    pub fn next_epoch_id(&self) -> Result<u64> {
+        let next = self
+            .last_epoch_id
+            .checked_add(1)
+            .ok_or(LottryError::MathOverflow)?;
+        // Stay inside this series' id range
+        require!(
+            next >> 32 == self.series_id as u64,
+            LottryError::SeriesExhausted
+        );
+        Ok(next)
+    }
+}
+
+/// Epoch template of a series. Sale and draw times are relative to when
+/// each epoch is opened.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SeriesParams {
+    pub base_price: u64,
+    pub pricing_curve: PricingCurve,
+    pub purchase_fee_bps: u16,
+    pub payout_fee_bps: u16,
+    pub draw_authority: Pubkey,
+    pub sale_duration_secs: i64,
+    /// Gap between the sale closing and the draw
+    pub draw_delay_secs: i64,
+    pub prize_tiers: Vec<u16>,
+    pub claim_window_secs: i64,
+    pub forfeit_policy: ForfeitPolicy,
+    pub game_mode: GameMode,
+    pub reveal_window_secs: i64,
+    pub draw_grace_secs: i64,
+}
+
+impl SeriesParams {
+    pub const LEN: usize = 8 + PricingCurve::LEN + 2 + 2 + 32 + 8 + 8 + (4 + MAX_PRIZE_TIERS * 2)
+        + 8 + 1 + GameMode::LEN + 8 + 8;
+
+    pub fn validate(&self) -> Result<()> {
+        require!(
+            self.sale_duration_secs > 0 && self.draw_delay_secs >= 0,
+            LottryError::InvalidSaleWindow
+        );
+        self.params_at(0)?.validate()
+    }
+
+    /// Concrete epoch parameters for a sale opening at `opens_at`.
+    pub fn params_at(&self, opens_at: i64) -> Result<LotteryParams> {
+        let sale_closes_at = opens_at
+            .checked_add(self.sale_duration_secs)
+            .ok_or(LottryError::MathOverflow)?;
+        let draw_at = sale_closes_at
+            .checked_add(self.draw_delay_secs)
+            .ok_or(LottryError::MathOverflow)?;
+        Ok(LotteryParams {
+            base_price: self.base_price,
+            pricing_curve: self.pricing_curve,
+            purchase_fee_bps: self.purchase_fee_bps,
+            payout_fee_bps: self.payout_fee_bps,
+            draw_authority: self.draw_authority,
+            sale_opens_at: opens_at,
+            sale_closes_at,
+            draw_at,
+            prize_tiers: self.prize_tiers.clone(),
+            claim_window_secs: self.claim_window_secs,
+            forfeit_policy: self.forfeit_policy,
+            game_mode: self.game_mode,
+            reveal_window_secs: self.reveal_window_secs,
+            draw_grace_secs: self.draw_grace_secs,
+        })
+    }
+}
+
+/// Fixed-point scale for exponential curve math.
+const CURVE_SCALE: u128 = 1_000_000_000;
+
+/// How the price of a credit grows with the number of credits already sold.
+/// Credit `i` (0-based) costs:
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PricingCurve {
+    /// `base_price`
+    Flat,
+    /// `base_price + slope * i`
+    Linear { slope: u64 },
+    /// `base_price * (1 + growth_bps / 10_000)^i`
+    Exponential { growth_bps: u16 },
+    /// `base_price + increment * (i / step_size)`
+    Stepped { step_size: u64, increment: u64 },
+}
+
+impl PricingCurve {
+    pub const LEN: usize = 1 + 8 + 8; // tag + largest variant
+
+    pub fn validate(&self) -> Result<()> {
+        if let PricingCurve::Stepped { step_size, .. } = self {
+            require!(*step_size > 0, LottryError::InvalidPricingCurve);
+        }
+        Ok(())
+    }
+
+    /// Total cost of the first `count` credits. Purchases are priced as
+    /// differences of this, so splitting a batch never changes its cost.
+    pub fn cumulative_cost(&self, base_price: u64, count: u64) -> Result<u128> {
+        let base = base_price as u128;
+        let n = count as u128;
+        let flat = base.checked_mul(n).ok_or(LottryError::MathOverflow)?;
+        let cost = match *self {
+            PricingCurve::Flat => Some(flat),
+            PricingCurve::Linear { slope } => {
+                // slope * (0 + 1 + … + (n - 1))
+                let steps = n * n.saturating_sub(1) / 2;
+                (slope as u128)
+                    .checked_mul(steps)
+                    .and_then(|extra| flat.checked_add(extra))
+            }
+            PricingCurve::Exponential { growth_bps } => {
+                if growth_bps == 0 {
+                    Some(flat)
+                } else {
+                    // Geometric series: base * (f^n - 1) / (f - 1), f = 1 + g
+                    let growth = CURVE_SCALE * growth_bps as u128 / 10_000;
+                    let factor_pow = fixed_pow(CURVE_SCALE + growth, count)?;
+                    (factor_pow - CURVE_SCALE)
+                        .checked_mul(CURVE_SCALE)
+                        .map(|scaled| scaled / growth)
+                        .and_then(|series| base.checked_mul(series))
+                        .map(|total| total / CURVE_SCALE)
+                }
+            }
+            PricingCurve::Stepped {
+                step_size,
+                increment,
+            } => {
+                // increment * Σ floor(i / step_size) for i in 0..n
+                let k = step_size as u128;
+                let (q, r) = (n / k, n % k);
+                k.checked_mul(q * q.saturating_sub(1) / 2)
+                    .and_then(|full| full.checked_add(r * q))
+                    .and_then(|steps| (increment as u128).checked_mul(steps))
+                    .and_then(|extra| flat.checked_add(extra))
+            }
+        };
+        cost.ok_or(LottryError::MathOverflow.into())
+    }
+}
+
+/// `factor^exp` for a `CURVE_SCALE` fixed-point `factor`.
+fn fixed_pow(mut factor: u128, mut exp: u64) -> Result<u128> {
+    let mul = |a: u128, b: u128| -> Result<u128> {
+        Ok(a.checked_mul(b).ok_or(LottryError::MathOverflow)? / CURVE_SCALE)
+    };
+    let mut result = CURVE_SCALE;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = mul(result, factor)?;
+        }
+        exp >>= 1;
+        if exp > 0 {
+            factor = mul(factor, factor)?;
+        }
+    }
+    Ok(result)
+}
+
+/// Token-side accounts of a mint-denominated pool.
+pub struct PoolTokenAccounts<'a, 'info> {
+    pub mint: &'a InterfaceAccount<'info, Mint>,
+    pub vault: &'a mut InterfaceAccount<'info, TokenAccount>,
+    /// The user side of the transfer (payer or recipient)
+    pub user: &'a InterfaceAccount<'info, TokenAccount>,
+    pub token_program: &'a Interface<'info, TokenInterface>,
+}
+
+/// Validate the optional token accounts against the pool's denomination.
+/// Returns `None` for native SOL pools.
+pub fn resolve_token_accounts<'a, 'info>(
+    pool: &LotteryPool,
+    mint: &'a Option<InterfaceAccount<'info, Mint>>,
+    vault: &'a mut Option<InterfaceAccount<'info, TokenAccount>>,
+    user: &'a Option<InterfaceAccount<'info, TokenAccount>>,
+    token_program: &'a Option<Interface<'info, TokenInterface>>,
+    user_owner: &Pubkey,
+) -> Result<Option<PoolTokenAccounts<'a, 'info>>> {
+    let Some(pool_mint) = pool.mint else {
+        return Ok(None);
+    };
+    let (Some(mint), Some(vault), Some(user), Some(token_program)) =
+        (mint.as_ref(), vault.as_mut(), user.as_ref(), token_program.as_ref())
+    else {
+        return err!(LottryError::MissingTokenAccounts);
+    };
+
+    require_keys_eq!(mint.key(), pool_mint, LottryError::InvalidMint);
+    require_keys_eq!(
+        *mint.to_account_info().owner,
+        token_program.key(),
+        LottryError::InvalidMint
+    );
+    require!(
+        pool.token_vault == Some(vault.key()),
+        LottryError::InvalidTokenAccount
+    );
+    require_keys_eq!(user.mint, pool_mint, LottryError::InvalidTokenAccount);
+    require_keys_eq!(user.owner, *user_owner, LottryError::InvalidTokenAccount);
+
+    Ok(Some(PoolTokenAccounts {
+        mint,
+        vault,
+        user,
+        token_program,
+    }))
+}
+
+/// Move `amount` from `payer` into the pool: lamports through the system
+/// program, or tokens into the vault. Returns what the pool received, which
+/// is less than `amount` for Token-2022 mints with a transfer fee.
+pub fn pay_into_pool<'info>(
+    pool: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    token: Option<&mut PoolTokenAccounts<'_, 'info>>,
+    amount: u64,
+) -> Result<u64> {
+    let Some(token) = token else {
+        let cpi_ctx = CpiContext::new(
+            system_program.clone(),
+            Transfer {
+                from: payer.clone(),
+                to: pool.clone(),
+            },
+        );
+        transfer(cpi_ctx, amount)?;
+        return Ok(amount);
+    };
+
+    let before = token.vault.amount;
+    let cpi_ctx = CpiContext::new(
+        token.token_program.to_account_info(),
+        TransferChecked {
+            from: token.user.to_account_info(),
+            mint: token.mint.to_account_info(),
+            to: token.vault.to_account_info(),
+            authority: payer.clone(),
+        },
+    );
+    transfer_checked(cpi_ctx, amount, token.mint.decimals)?;
+    token.vault.reload()?;
+    Ok(token
+        .vault
+        .amount
+        .checked_sub(before)
+        .ok_or(LottryError::MathOverflow)?)
+}
+
+/// Move `amount` out of the pool to `recipient`: a direct lamport debit, or a
+/// vault transfer signed by the pool PDA.
+pub fn pay_out_of_pool<'info>(
+    pool: &Account<'info, LotteryPool>,
+    pool_bump: u8,
+    recipient: &AccountInfo<'info>,
+    token: Option<&mut PoolTokenAccounts<'_, 'info>>,
+    amount: u64,
+) -> Result<()> {
+    let Some(token) = token else {
+        **pool.to_account_info().try_borrow_mut_lamports()? -= amount;
+        **recipient.try_borrow_mut_lamports()? += amount;
+        return Ok(());
+    };
+
+    let epoch_bytes = pool.epoch_id.to_le_bytes();
+    let signer_seeds: &[&[&[u8]]] = &[&[LOTTERY_POOL_SEED, &epoch_bytes, &[pool_bump]]];
+    let cpi_ctx = CpiContext::new_with_signer(
+        token.token_program.to_account_info(),
+        TransferChecked {
+            from: token.vault.to_account_info(),
+            mint: token.mint.to_account_info(),
+            to: token.user.to_account_info(),
+            authority: pool.to_account_info(),
+        },
+        signer_seeds,
+    );
+    transfer_checked(cpi_ctx, amount, token.mint.decimals)?;
+    token.vault.reload()?;
+    Ok(())
+}
+
+/// Carry `amount` of `pool`'s stake into `next_pool`'s prize pool, booking
+/// what `next_pool`'s vault actually receives (less than `amount` for
+/// Token-2022 mints with a transfer fee). The caller has already checked
+/// `next_pool` with `require_rollover_target`.
+pub fn roll_into<'info>(
+    pool: &mut Account<'info, LotteryPool>,
+    pool_bump: u8,
+    next_pool: &mut Account<'info, LotteryPool>,
+    mut token: Option<&mut PoolTokenAccounts<'_, 'info>>,
+    amount: u64,
+) -> Result<()> {
+    // For token pools the transfer's user side is `next_pool`'s vault
+    let next_vault_before = token.as_deref().map(|token| token.user.amount);
+    pay_out_of_pool(
+        pool,
+        pool_bump,
+        &next_pool.to_account_info(),
+        token.as_deref_mut(),
+        amount,
+    )?;
+
+    pool.total_staked_sol = pool
+        .total_staked_sol
+        .checked_sub(amount)
+        .ok_or(LottryError::MathOverflow)?;
+    pool.rolled_over_to = Some(next_pool.epoch_id);
+    pool.assert_solvent(&pool.to_account_info(), token.as_deref())?;
+
+    let (received, next_vault_amount) = match (token.as_deref(), next_vault_before) {
+        (Some(token), Some(before)) => {
+            let info = token.user.to_account_info();
+            let after = TokenAccount::try_deserialize(&mut &info.try_borrow_data()?[..])?.amount;
+            let received = after
+                .checked_sub(before)
+                .ok_or(LottryError::MathOverflow)?;
+            (received, Some(after))
+        }
+        _ => (amount, None),
+    };
+    next_pool.total_staked_sol = next_pool
+        .total_staked_sol
+        .checked_add(received)
+        .ok_or(LottryError::MathOverflow)?;
+    next_pool.rolled_in_sol = next_pool
+        .rolled_in_sol
+        .checked_add(received)
+        .ok_or(LottryError::MathOverflow)?;
+    next_pool.assert_holds(&next_pool.to_account_info(), next_vault_amount)?;
+    Ok(())
+}
+
+/// Check that a raw account is a writable `PlayerTicket` of `epoch_id`
+/// before it is committed alongside its pool.
+pub fn require_player_ticket(account: &AccountInfo, epoch_id: u64) -> Result<()> {
+    require!(account.is_writable, LottryError::AccountNotWritable);
+    require_keys_eq!(*account.owner, crate::ID, LottryError::InvalidPlayerTicket);
+    let ticket = PlayerTicket::try_deserialize(&mut &account.try_borrow_data()?[..])
+        .map_err(|_| LottryError::InvalidPlayerTicket)?;
+    require!(ticket.epoch_id == epoch_id, LottryError::EpochMismatch);
+    Ok(())
+}
+
+/// Commitment a pick-number entry stores as `ticket_data`:
+/// `sha256(number_le || salt)`.
+pub fn ticket_commitment(number: u32, salt: &[u8; 32]) -> [u8; 32] {
+    hashv(&[&number.to_le_bytes(), salt]).to_bytes()
+}
+
+/// `amount * bps / 10_000`, rounded down.
+pub fn bps_of(amount: u64, bps: u16) -> Result<u64> {
+    let value = (amount as u128)
+        .checked_mul(bps as u128)
+        .ok_or(LottryError::MathOverflow)?
+        / 10_000u128;
+    require!(value <= u64::MAX as u128, LottryError::MathOverflow);
+    Ok(value as u64)
+}
+
+/// Individual participant ticket — shielded in TEE.
+/// One account per player per epoch; each spent credit adds an entry.
+#[account]
+pub struct PlayerTicket {
+    pub owner: Pubkey,             // 32
+    pub epoch_id: u64,             // 8
+    pub entries: Vec<TicketEntry>, // 4 + MAX_TICKETS_PER_PLAYER * TicketEntry::LEN
+    pub balance: u64,              // 8
+    pub paid_sol: u64,             // 8 — lamports paid in, refundable on cancel
+    pub paid_tax_sol: u64,         // 8 — portion of paid_sol booked as tax
+    pub is_active: bool,           // 1
+    pub claimed_tiers: u8,         // 1 — bitmask of Pick-N tiers this ticket was paid
+}
+
+impl PlayerTicket {
+    pub const LEN: usize =
+        8 + 32 + 8 + (4 + MAX_TICKETS_PER_PLAYER * TicketEntry::LEN) + 8 + 8 + 8 + 1 + 1;
+
+    /// Whether one of this player's entries carries `ticket_id`.
+    pub fn holds(&self, ticket_id: u64) -> bool {
+        self.entries
+            .iter()
+            .any(|e| (e.ticket_id..e.ticket_id.saturating_add(e.count)).contains(&ticket_id))
+    }
+
+    pub fn is_tier_claimed(&self, tier: u8) -> bool {
+        self.claimed_tiers & (1 << tier) != 0
+    }
+}
+
+/// A single entry in the draw, bought with one or more credits.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct TicketEntry {
+    pub ticket_id: u64,        // 8 — first of the entry's ticket ids
+    pub ticket_data: [u8; 32], // 32 (hashed/shielded entry; a commitment in pick-number pools)
+    pub revealed: Option<u32>, // 1 + 4 — number opened by `reveal_ticket`
+    pub matches: Option<u8>,   // 1 + 1 — set by `register_ticket` in Pick-N pools
+    pub weight: u64,           // 8 — credits committed; above 1 in weighted and random-ticket pools
+    pub count: u64,            // 8 — ticket ids from ticket_id; above 1 only in random-ticket pools
+}
+
+impl TicketEntry {
+    pub const LEN: usize = 8 + 32 + (1 + 4) + (1 + 1) + 8 + 8;
+}
+
+/// Fenwick (binary indexed) tree over the entry weights of a weighted
+/// epoch; ticket `i` is node `i + 1`. Zero-copy, so `buy_ticket` and the
+/// draw touch O(log n) nodes instead of deserializing the whole tree.
+#[account(zero_copy)]
+pub struct WeightTree {
+    pub epoch_id: u64,                            // 8
+    pub total_weight: u64,                        // 8
+    pub nodes: [u64; WEIGHT_TREE_CAPACITY],       // WEIGHT_TREE_CAPACITY * 8
+}
+
+impl WeightTree {
+    pub const LEN: usize = 8 + 8 + 8 + WEIGHT_TREE_CAPACITY * 8;
+
+    /// Add `weight` to `ticket_id`'s entry.
+    pub fn add(&mut self, ticket_id: u64, weight: u64) -> Result<()> {
+        let mut node = ticket_id as usize + 1;
+        require!(node <= WEIGHT_TREE_CAPACITY, LottryError::WeightTreeFull);
+        while node <= WEIGHT_TREE_CAPACITY {
+            self.nodes[node - 1] = self.nodes[node - 1]
+                .checked_add(weight)
+                .ok_or(LottryError::MathOverflow)?;
+            node += node & node.wrapping_neg();
+        }
+        self.total_weight = self
+            .total_weight
+            .checked_add(weight)
+            .ok_or(LottryError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Take `weight` back off `ticket_id`'s entry.
+    fn remove(&mut self, ticket_id: u64, weight: u64) -> Result<()> {
+        let mut node = ticket_id as usize + 1;
+        while node <= WEIGHT_TREE_CAPACITY {
+            self.nodes[node - 1] = self.nodes[node - 1]
+                .checked_sub(weight)
+                .ok_or(LottryError::MathOverflow)?;
+            node += node & node.wrapping_neg();
+        }
+        self.total_weight = self
+            .total_weight
+            .checked_sub(weight)
+            .ok_or(LottryError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Total weight of tickets `0..count`.
+    fn prefix_sum(&self, count: usize) -> u64 {
+        let mut node = count;
+        let mut sum = 0;
+        while node > 0 {
+            sum += self.nodes[node - 1];
+            node &= node - 1;
+        }
+        sum
+    }
+
+    pub fn weight_of(&self, ticket_id: u64) -> u64 {
+        let id = ticket_id as usize;
+        self.prefix_sum(id + 1) - self.prefix_sum(id)
+    }
+
+    /// The ticket whose slice of the cumulative weight contains `target`
+    /// (`< total_weight`).
+    pub fn find(&self, target: u64) -> u64 {
+        let mut node = 0;
+        let mut remaining = target;
+        let mut step = WEIGHT_TREE_CAPACITY;
+        while step > 0 {
+            let next = node + step;
+            if next <= WEIGHT_TREE_CAPACITY && self.nodes[next - 1] <= remaining {
+                node = next;
+                remaining -= self.nodes[next - 1];
+            }
+            step >>= 1;
+        }
+        node as u64
+    }
+
+    /// Derive `count` distinct winning tickets, one per prize tier, from a
+    /// single VRF output. Each winner's weight is held out of later tiers'
+    /// draws and restored afterwards.
+    pub fn draw_winners(&mut self, randomness: &[u8; 32], count: usize) -> Result<Vec<u64>> {
+        require!(self.total_weight > 0, LottryError::NoTickets);
+        let mut winners: Vec<(u64, u64)> = Vec::with_capacity(count);
+        for tier in 0..count as u8 {
+            let digest = hashv(&[randomness, &[tier]]).to_bytes();
+            let mut value = [0u8; 16];
+            value.copy_from_slice(&digest[..16]);
+            let target = (u128::from_le_bytes(value) % self.total_weight as u128) as u64;
+            let id = self.find(target);
+            let weight = self.weight_of(id);
+            self.remove(id, weight)?;
+            winners.push((id, weight));
+        }
+        for (id, weight) in winners.iter() {
+            self.add(*id, *weight)?;
+        }
+        Ok(winners.into_iter().map(|(id, _)| id).collect())
+    }
+}
+
+/// Session token — secondary signer PDA for frictionless UX.
+#[account]
+pub struct SessionToken {
+    pub authority: Pubkey,     // 32 — primary wallet
+    pub ephemeral_key: Pubkey, // 32 — temp client-side keypair
+    pub valid_until: i64,      // 8  — unix timestamp expiry
+    pub is_revoked: bool,      // 1
+    pub scope: SessionScope,   // SessionScope::LEN
+    pub credits_remaining: Option<u64>, // 1 + 8 — None when the scope has no credit cap
+}
+
+impl SessionToken {
+    pub const LEN: usize = 8 + 32 + 32 + 8 + 1 + SessionScope::LEN + (1 + 8);
+
+    /// Check that `signer` is this session's key and the session is live.
+    pub fn require_usable(&self, signer: &Pubkey, now: i64) -> Result<()> {
+        require!(
+            self.ephemeral_key == *signer,
+            LottryError::InvalidSessionSigner
+        );
+        require!(!self.is_revoked, LottryError::SessionRevoked);
+        require!(now < self.valid_until, LottryError::SessionExpired);
+        Ok(())
+    }
+
+    /// Check that the scope allows `instruction` (a `SESSION_SCOPE_*` bit) on
+    /// this epoch and pool.
+    pub fn require_scope(&self, instruction: u8, epoch_id: u64, pool: &Pubkey) -> Result<()> {
+        require!(
+            self.scope.instructions & instruction != 0
+                && self.scope.epoch_id.is_none_or(|id| id == epoch_id)
+                && self.scope.pool.is_none_or(|key| key == *pool),
+            LottryError::SessionScopeViolation
+        );
+        Ok(())
+    }
+
+    /// Draw `credits` from the session's allowance, if it has one.
+    pub fn spend_credits(&mut self, credits: u64) -> Result<()> {
+        if let Some(remaining) = self.credits_remaining {
+            require!(remaining >= credits, LottryError::SessionCreditLimitReached);
+            self.credits_remaining = Some(remaining - credits);
+        }
+        Ok(())
+    }
+}
+
+/// What a session key may do.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SessionScope {
+    /// Bitmask of `SESSION_SCOPE_*` instructions
+    pub instructions: u8,
+    /// Only this epoch, if set
+    pub epoch_id: Option<u64>,
+    /// Only this lottery pool, if set
+    pub pool: Option<Pubkey>,
+    /// Credits the key may spend in `buy_ticket`; None for no cap
+    pub max_credits: Option<u64>,
+}
+
+impl SessionScope {
+    pub const LEN: usize = 1 + (1 + 8) + (1 + 32) + (1 + 8);
+
+    pub fn validate(&self) -> Result<()> {
+        let known = SESSION_SCOPE_BUY_TICKET | SESSION_SCOPE_REQUEST_RANDOMNESS;
+        require!(
+            self.instructions != 0 && self.instructions & !known == 0,
+            LottryError::InvalidSessionScope
+        );
+        Ok(())
+    }
+}
+
+// ──────────────────────────────────────────────────────────────────────────────
+// Contexts
+// ──────────────────────────────────────────────────────────────────────────────
+
+// ── Program Config ───────────────────────────────────────────────────────────
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct InitConfig<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = ProgramConfig::LEN,
+        seeds = [CONFIG_SEED],
+        bump
+    )]
+    pub config: Account<'info, ProgramConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(constraint = lotry_program.programdata_address()? == Some(program_data.key()))]
+    pub lotry_program: Program<'info, crate::program::Lotry>,
+    #[account(constraint = program_data.upgrade_authority_address == Some(admin.key()) @ LottryError::Unauthorized)]
+    pub program_data: Account<'info, ProgramData>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    #[account(
+        mut,
+        seeds = [CONFIG_SEED],
+        bump
+    )]
+    pub config: Account<'info, ProgramConfig>,
+    pub admin: Signer<'info>,
+}
+
+// ── Phase 1 ──────────────────────────────────────────────────────────────────
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(epoch_id: u64)]
+pub struct InitializeLottery<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = LotteryPool::LEN,
+        seeds = [LOTTERY_POOL_SEED, &epoch_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery_pool: Account<'info, LotteryPool>,
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: Account<'info, ProgramConfig>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// ── Series ───────────────────────────────────────────────────────────────────
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(series_id: u32)]
+pub struct CreateSeries<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = LotterySeries::LEN,
+        seeds = [SERIES_SEED, &series_id.to_le_bytes()],
+        bump
+    )]
+    pub series: Account<'info, LotterySeries>,
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: Account<'info, ProgramConfig>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(series_id: u32)]
+pub struct OpenNextEpoch<'info> {
+    #[account(
+        mut,
+        seeds = [SERIES_SEED, &series_id.to_le_bytes()],
+        bump
+    )]
+    pub series: Account<'info, LotterySeries>,
+    #[account(
+        init,
+        payer = payer,
+        space = LotteryPool::LEN,
+        seeds = [LOTTERY_POOL_SEED, &series.next_epoch_id()?.to_le_bytes()],
+        bump
+    )]
+    pub lottery_pool: Account<'info, LotteryPool>,
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: Account<'info, ProgramConfig>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// ── Phase 2 ──────────────────────────────────────────────────────────────────
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(epoch_id: u64)]
+pub struct DelegateLottery<'info> {
+    #[account(
+        mut,
+        seeds = [LOTTERY_POOL_SEED, &epoch_id.to_le_bytes()],
+        bump,
+    )]
+    /// CHECK: delegating pda
+    pub lottery_pool: Account<'info, LotteryPool>,
+
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: Account<'info, ProgramConfig>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    /// CHECK: Must be an allowlisted ER validator
+    pub validator: Option<AccountInfo<'info>>,
+
+    /// CHECK: The buffer account - created via CPI
+    #[account(mut)]
+    pub buffer_lottery_pool: AccountInfo<'info>,
+
+    /// CHECK: The delegation record account - created via CPI
+    #[account(mut)]
+    pub delegation_record_lottery_pool: AccountInfo<'info>,
+
+    /// CHECK: The delegation metadata account - created via CPI
+    #[account(mut)]
+    pub delegation_metadata_lottery_pool: AccountInfo<'info>,
+
+    /// CHECK: The delegation program
+    #[account(address = ephemeral_rollups_sdk::consts::DELEGATION_PROGRAM_ID)]
+    pub delegation_program: AccountInfo<'info>,
+
+    /// CHECK: The owner program
+    #[account(address = crate::id())]
+    pub owner_program: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// ── Phase 2 ──────────────────────────────────────────────────────────────────
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(epoch_id: u64, ticket_amount: u64)]
+pub struct BuyTicketCredits<'info> {
+    #[account(
+        mut,
+        seeds = [LOTTERY_POOL_SEED, &epoch_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery_pool: Account<'info, LotteryPool>,
+    #[account(
+        mut,
+        seeds = [PLAYER_TICKET_SEED, buyer.key().as_ref(), &epoch_id.to_le_bytes()],
+        bump
+    )]
+    pub player_ticket: Account<'info, PlayerTicket>,
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    // Token pools only
+    pub mint: Option<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub pool_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub buyer_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(epoch_id: u64)]
+pub struct InitPoolVault<'info> {
+    #[account(
+        mut,
+        seeds = [LOTTERY_POOL_SEED, &epoch_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery_pool: Account<'info, LotteryPool>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        payer = authority,
+        seeds = [POOL_VAULT_SEED, lottery_pool.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = lottery_pool,
+        token::token_program = token_program
+    )]
+    pub pool_vault: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(epoch_id: u64)]
+pub struct QuoteTicketCredits<'info> {
+    #[account(
+        seeds = [LOTTERY_POOL_SEED, &epoch_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery_pool: Account<'info, LotteryPool>,
+}
+
+// ── Phase 3 ──────────────────────────────────────────────────────────────────
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(ephemeral_key: Pubkey)]
+pub struct IssueSession<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = SessionToken::LEN,
+        seeds = [SESSION_SEED, authority.key().as_ref(), ephemeral_key.as_ref()],
+        bump
+    )]
+    pub session_token: Account<'info, SessionToken>,
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: Account<'info, ProgramConfig>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct RenewSession<'info> {
+    #[account(
+        mut,
+        seeds = [SESSION_SEED, authority.key().as_ref(), session_token.ephemeral_key.as_ref()],
+        bump
+    )]
+    pub session_token: Account<'info, SessionToken>,
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: Account<'info, ProgramConfig>,
+    pub authority: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct DelegateSession<'info> {
+    #[account(
+        mut,
+        seeds = [SESSION_SEED, authority.key().as_ref(), session_token.ephemeral_key.as_ref()],
+        bump
+    )]
+    pub session_token: Account<'info, SessionToken>,
+
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    /// CHECK: Must be an allowlisted ER validator
+    pub validator: Option<AccountInfo<'info>>,
+
+    /// CHECK: The buffer account - created via CPI
+    #[account(mut)]
+    pub buffer_session_token: AccountInfo<'info>,
+
+    /// CHECK: The delegation record account - created via CPI
+    #[account(mut)]
+    pub delegation_record: AccountInfo<'info>,
+
+    /// CHECK: The delegation metadata account - created via CPI
+    #[account(mut)]
+    pub delegation_metadata: AccountInfo<'info>,
+
+    /// CHECK: The delegation program
+    #[account(address = ephemeral_rollups_sdk::consts::DELEGATION_PROGRAM_ID)]
+    pub delegation_program: AccountInfo<'info>,
+
+    /// CHECK: The owner program
+    #[account(address = crate::id())]
+    pub owner_program: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct UndelegateSession<'info> {
+    #[account(
+        mut,
+        seeds = [SESSION_SEED, authority.key().as_ref(), session_token.ephemeral_key.as_ref()],
+        bump
+    )]
+    pub session_token: Account<'info, SessionToken>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    /// CHECK: Magic context must be mutable for the schedule commit invocation
+    #[account(mut)]
+    pub magic_context: AccountInfo<'info>,
+
+    /// CHECK: Magic program executable
+    pub magic_program: AccountInfo<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct RevokeSession<'info> {
+    #[account(
+        mut,
+        seeds = [SESSION_SEED, authority.key().as_ref(), session_token.ephemeral_key.as_ref()],
+        bump
+    )]
+    pub session_token: Account<'info, SessionToken>,
+    pub authority: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CloseSession<'info> {
+    #[account(
+        mut,
+        close = authority,
+        seeds = [SESSION_SEED, authority.key().as_ref(), session_token.ephemeral_key.as_ref()],
+        bump
+    )]
+    pub session_token: Account<'info, SessionToken>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+// ── Phase 4 ──────────────────────────────────────────────────────────────────
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(epoch_id: u64)]
+pub struct InitPlayerTicket<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = PlayerTicket::LEN,
+        seeds = [PLAYER_TICKET_SEED, authority.key().as_ref(), &epoch_id.to_le_bytes()],
+        bump
+    )]
+    pub player_ticket: Account<'info, PlayerTicket>,
+    #[account(
+        mut,
+        seeds = [LOTTERY_POOL_SEED, &epoch_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery_pool: Account<'info, LotteryPool>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(epoch_id: u64)]
+pub struct DelegatePlayerTicket<'info> {
+    #[account(
+        mut,
+        seeds = [PLAYER_TICKET_SEED, authority.key().as_ref(), &epoch_id.to_le_bytes()],
+        bump
+    )]
+    pub player_ticket: Account<'info, PlayerTicket>,
+
+    /// CHECK: Usually owned by the delegation program already; seeds pin the
+    /// epoch and the handler deserializes it to read the pool's validator
+    #[account(
+        seeds = [LOTTERY_POOL_SEED, &epoch_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery_pool: UncheckedAccount<'info>,
+    
+    #[account(mut)]
     pub authority: Signer<'info>,
 
-    /// CHECK: Checked by the delegate program — target ER validator
+    /// CHECK: Must match the validator the pool was delegated to
     pub validator: Option<AccountInfo<'info>>,
 
     /// CHECK: The buffer account - created via CPI
     #[account(mut)]
-    pub buffer_lottery_pool: AccountInfo<'info>,
+    pub buffer_player_ticket: AccountInfo<'info>,
 
     /// CHECK: The delegation record account - created via CPI
     #[account(mut)]
-    pub delegation_record_lottery_pool: AccountInfo<'info>,
+    pub delegation_record: AccountInfo<'info>,
 
     /// CHECK: The delegation metadata account - created via CPI
     #[account(mut)]
-    pub delegation_metadata_lottery_pool: AccountInfo<'info>,
+    pub delegation_metadata: AccountInfo<'info>,
 
-    /// CHECK: The delegation program
+    /// CHECK: Passed to the CPI
     #[account(address = ephemeral_rollups_sdk::consts::DELEGATION_PROGRAM_ID)]
-    pub delegation_program: AccountInfo<'info>,
+    pub ephemeral_rollups_program: AccountInfo<'info>,
 
     /// CHECK: The owner program
     #[account(address = crate::id())]
@@ -597,169 +3590,449 @@ pub struct DelegateLottery<'info> {
     pub system_program: Program<'info, System>,
 }
 
-// ── Phase 2 ──────────────────────────────────────────────────────────────────
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(epoch_id: u64)]
+pub struct InitWeightTree<'info> {
+    #[account(
+        seeds = [LOTTERY_POOL_SEED, &epoch_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery_pool: Account<'info, LotteryPool>,
+    #[account(
+        init,
+        payer = authority,
+        space = WeightTree::LEN,
+        seeds = [WEIGHT_TREE_SEED, &epoch_id.to_le_bytes()],
+        bump
+    )]
+    pub weight_tree: AccountLoader<'info, WeightTree>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
 
+#[event_cpi]
 #[derive(Accounts)]
-#[instruction(epoch_id: u64, ticket_amount: u64)]
-pub struct BuyTicketCredits<'info> {
+#[instruction(epoch_id: u64)]
+pub struct DelegateWeightTree<'info> {
     #[account(
         mut,
+        seeds = [WEIGHT_TREE_SEED, &epoch_id.to_le_bytes()],
+        bump
+    )]
+    pub weight_tree: AccountLoader<'info, WeightTree>,
+
+    /// CHECK: Usually owned by the delegation program already; seeds pin the
+    /// epoch and the handler deserializes it to read the pool's validator
+    #[account(
         seeds = [LOTTERY_POOL_SEED, &epoch_id.to_le_bytes()],
         bump
     )]
+    pub lottery_pool: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    /// CHECK: Must match the validator the pool was delegated to
+    pub validator: Option<AccountInfo<'info>>,
+
+    /// CHECK: The buffer account - created via CPI
+    #[account(mut)]
+    pub buffer_weight_tree: AccountInfo<'info>,
+
+    /// CHECK: The delegation record account - created via CPI
+    #[account(mut)]
+    pub delegation_record: AccountInfo<'info>,
+
+    /// CHECK: The delegation metadata account - created via CPI
+    #[account(mut)]
+    pub delegation_metadata: AccountInfo<'info>,
+
+    /// CHECK: Passed to the CPI
+    #[account(address = ephemeral_rollups_sdk::consts::DELEGATION_PROGRAM_ID)]
+    pub delegation_program: AccountInfo<'info>,
+
+    /// CHECK: The owner program
+    #[account(address = crate::id())]
+    pub owner_program: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(epoch_id: u64, ticket_data: [u8; 32])]
+pub struct BuyTicket<'info> {
+    #[account(mut)]
     pub lottery_pool: Account<'info, LotteryPool>,
     #[account(
         mut,
-        seeds = [PLAYER_TICKET_SEED, buyer.key().as_ref(), &epoch_id.to_le_bytes()],
+        seeds = [PLAYER_TICKET_SEED, authority.key().as_ref(), &epoch_id.to_le_bytes()],
         bump
     )]
     pub player_ticket: Account<'info, PlayerTicket>,
+    /// CHECK:
+    pub authority: UncheckedAccount<'info>,
     #[account(mut)]
-    pub buyer: Signer<'info>,
+    pub session_token: Account<'info, SessionToken>,
+    pub ephemeral_signer: Signer<'info>,
+    pub fee_payer: Signer<'info>,
     pub system_program: Program<'info, System>,
+    // Weighted pools only
+    #[account(
+        mut,
+        seeds = [WEIGHT_TREE_SEED, &epoch_id.to_le_bytes()],
+        bump
+    )]
+    pub weight_tree: Option<AccountLoader<'info, WeightTree>>,
 }
 
-// ── Phase 3 ──────────────────────────────────────────────────────────────────
+// ── Phase 5 ──────────────────────────────────────────────────────────────────
 
+#[vrf]
+#[event_cpi]
 #[derive(Accounts)]
-#[instruction(ephemeral_key: Pubkey, valid_until: i64)]
-pub struct IssueSession<'info> {
+#[instruction(epoch_id: u64)]
+pub struct RequestRandomness<'info> {
     #[account(
-        init,
-        payer = authority,
-        space = SessionToken::LEN,
-        seeds = [SESSION_SEED, authority.key().as_ref(), ephemeral_key.as_ref()],
+        mut,
+        seeds = [LOTTERY_POOL_SEED, &epoch_id.to_le_bytes()],
         bump
     )]
-    pub session_token: Account<'info, SessionToken>,
+    pub lottery_pool: Account<'info, LotteryPool>,
+    /// Optional: draw on behalf of the authority or crank via a session key
+    pub session_token: Option<Account<'info, SessionToken>>,
+    pub ephemeral_signer: Option<Signer<'info>>,
     #[account(mut)]
-    pub authority: Signer<'info>,
-    pub system_program: Program<'info, System>,
+    pub payer: Signer<'info>,
+    /// CHECK: The ER oracle queue
+    #[account(mut, address = ephemeral_vrf_sdk::consts::DEFAULT_EPHEMERAL_QUEUE)]
+    pub oracle_queue: AccountInfo<'info>,
 }
 
-// ── Phase 4 ──────────────────────────────────────────────────────────────────
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ConsumeRandomness<'info> {
+    /// Signer PDA of the VRF program — proves the callback came through its CPI
+    #[account(address = ephemeral_vrf_sdk::consts::VRF_PROGRAM_IDENTITY)]
+    pub vrf_program_identity: Signer<'info>,
+    #[account(mut)]
+    pub lottery_pool: Account<'info, LotteryPool>,
+    // Weighted pools only
+    #[account(
+        mut,
+        seeds = [WEIGHT_TREE_SEED, &lottery_pool.epoch_id.to_le_bytes()],
+        bump
+    )]
+    pub weight_tree: Option<AccountLoader<'info, WeightTree>>,
+    // Event CPI accounts follow, in the order `request_randomness` passes them
+}
 
+// ── Phase 6 ──────────────────────────────────────────────────────────────────
+
+#[event_cpi]
 #[derive(Accounts)]
 #[instruction(epoch_id: u64)]
-pub struct InitPlayerTicket<'info> {
+pub struct CheckpointPool<'info> {
+    #[account(
+        mut,
+        seeds = [LOTTERY_POOL_SEED, &epoch_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery_pool: Account<'info, LotteryPool>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: Magic context must be mutable for the schedule commit invocation
+    #[account(mut)]
+    pub magic_context: AccountInfo<'info>,
+
+    /// CHECK: Magic program executable
+    pub magic_program: AccountInfo<'info>,
+
+    // Weighted pools only
+    #[account(
+        mut,
+        seeds = [WEIGHT_TREE_SEED, &epoch_id.to_le_bytes()],
+        bump
+    )]
+    pub weight_tree: Option<AccountLoader<'info, WeightTree>>,
+}
+
+// Removed #[commit] macro to allow explicit mutability and configurable Magic IDs 
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(epoch_id: u64)]
+pub struct UndelegatePool<'info> {
+    #[account(
+        mut,
+        seeds = [LOTTERY_POOL_SEED, &epoch_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery_pool: Account<'info, LotteryPool>,
+    
+    #[account(mut)] // Payer needs to be mutable for lamport transfers during commit
+    pub payer: Signer<'info>,
+    
+    /// CHECK: Magic context must be mutable for the schedule commit invocation
+    #[account(mut)]
+    pub magic_context: AccountInfo<'info>,
+    
+    /// CHECK: Magic program executable
+    pub magic_program: AccountInfo<'info>,
+
+    // Weighted pools only
+    #[account(
+        mut,
+        seeds = [WEIGHT_TREE_SEED, &epoch_id.to_le_bytes()],
+        bump
+    )]
+    pub weight_tree: Option<AccountLoader<'info, WeightTree>>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(epoch_id: u64)]
+pub struct UndelegatePlayerTicket<'info> {
+    #[account(
+        mut,
+        seeds = [PLAYER_TICKET_SEED, owner.key().as_ref(), &epoch_id.to_le_bytes()],
+        bump
+    )]
+    pub player_ticket: Account<'info, PlayerTicket>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// CHECK: Magic context must be mutable for the schedule commit invocation
+    #[account(mut)]
+    pub magic_context: AccountInfo<'info>,
+
+    /// CHECK: Magic program executable
+    pub magic_program: AccountInfo<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(epoch_id: u64)]
+pub struct UndelegatePlayerTickets<'info> {
+    #[account(
+        seeds = [LOTTERY_POOL_SEED, &epoch_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery_pool: Account<'info, LotteryPool>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: Magic context must be mutable for the schedule commit invocation
+    #[account(mut)]
+    pub magic_context: AccountInfo<'info>,
+
+    /// CHECK: Magic program executable
+    pub magic_program: AccountInfo<'info>,
+}
+
+// ── Phase 7 ──────────────────────────────────────────────────────────────────
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(epoch_id: u64)]
+pub struct RevealTicket<'info> {
+    #[account(
+        mut,
+        seeds = [LOTTERY_POOL_SEED, &epoch_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery_pool: Account<'info, LotteryPool>,
+    #[account(
+        mut,
+        seeds = [PLAYER_TICKET_SEED, owner.key().as_ref(), &epoch_id.to_le_bytes()],
+        bump
+    )]
+    pub player_ticket: Account<'info, PlayerTicket>,
+    pub owner: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(epoch_id: u64)]
+pub struct RegisterTicket<'info> {
+    #[account(
+        mut,
+        seeds = [LOTTERY_POOL_SEED, &epoch_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery_pool: Account<'info, LotteryPool>,
+    #[account(
+        mut,
+        seeds = [PLAYER_TICKET_SEED, owner.key().as_ref(), &epoch_id.to_le_bytes()],
+        bump
+    )]
+    pub player_ticket: Account<'info, PlayerTicket>,
+    pub owner: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(epoch_id: u64)]
+pub struct ClaimPrize<'info> {
+    #[account(
+        mut,
+        seeds = [LOTTERY_POOL_SEED, &epoch_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery_pool: Account<'info, LotteryPool>,
     #[account(
-        init,
-        payer = authority,
-        space = PlayerTicket::LEN,
-        seeds = [PLAYER_TICKET_SEED, authority.key().as_ref(), &epoch_id.to_le_bytes()],
+        mut,
+        seeds = [PLAYER_TICKET_SEED, winner.key().as_ref(), &epoch_id.to_le_bytes()],
         bump
     )]
     pub player_ticket: Account<'info, PlayerTicket>,
     #[account(mut)]
-    pub authority: Signer<'info>,
+    pub winner: Signer<'info>,
     pub system_program: Program<'info, System>,
+    // Token pools only
+    pub mint: Option<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub pool_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub winner_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
 }
 
+#[event_cpi]
 #[derive(Accounts)]
 #[instruction(epoch_id: u64)]
-pub struct DelegatePlayerTicket<'info> {
+pub struct WithdrawTaxes<'info> {
     #[account(
         mut,
-        seeds = [PLAYER_TICKET_SEED, authority.key().as_ref(), &epoch_id.to_le_bytes()],
+        seeds = [LOTTERY_POOL_SEED, &epoch_id.to_le_bytes()],
         bump
     )]
-    pub player_ticket: Account<'info, PlayerTicket>,
-    
+    pub lottery_pool: Account<'info, LotteryPool>,
     #[account(mut)]
     pub authority: Signer<'info>,
-
-    /// CHECK: Checked by the delegate program — target ER validator
-    pub validator: Option<AccountInfo<'info>>,
-
-    /// CHECK: The buffer account - created via CPI
     #[account(mut)]
-    pub buffer_player_ticket: AccountInfo<'info>,
-
-    /// CHECK: The delegation record account - created via CPI
+    pub treasury: SystemAccount<'info>,
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: Account<'info, ProgramConfig>,
+    #[account(mut, address = config.fee_recipient)]
+    pub fee_recipient: SystemAccount<'info>,
+    pub system_program: Program<'info, System>,
+    // Token pools only
+    pub mint: Option<InterfaceAccount<'info, Mint>>,
     #[account(mut)]
-    pub delegation_record: AccountInfo<'info>,
-
-    /// CHECK: The delegation metadata account - created via CPI
+    pub pool_vault: Option<InterfaceAccount<'info, TokenAccount>>,
     #[account(mut)]
-    pub delegation_metadata: AccountInfo<'info>,
-
-    /// CHECK: Passed to the CPI
-    #[account(address = ephemeral_rollups_sdk::consts::DELEGATION_PROGRAM_ID)]
-    pub ephemeral_rollups_program: AccountInfo<'info>,
+    pub treasury_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub fee_recipient_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+}
 
-    /// CHECK: The owner program
-    #[account(address = crate::id())]
-    pub owner_program: AccountInfo<'info>,
+// ── Refunds ──────────────────────────────────────────────────────────────────
 
-    pub system_program: Program<'info, System>,
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(epoch_id: u64)]
+pub struct CancelEpoch<'info> {
+    #[account(
+        mut,
+        seeds = [LOTTERY_POOL_SEED, &epoch_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery_pool: Account<'info, LotteryPool>,
+    pub authority: Signer<'info>,
 }
 
+#[event_cpi]
 #[derive(Accounts)]
-#[instruction(epoch_id: u64, ticket_data: [u8; 32])]
-pub struct BuyTicket<'info> {
-    #[account(mut)]
+#[instruction(epoch_id: u64)]
+pub struct ClaimRefund<'info> {
+    #[account(
+        mut,
+        seeds = [LOTTERY_POOL_SEED, &epoch_id.to_le_bytes()],
+        bump
+    )]
     pub lottery_pool: Account<'info, LotteryPool>,
     #[account(
         mut,
-        seeds = [PLAYER_TICKET_SEED, authority.key().as_ref(), &epoch_id.to_le_bytes()],
+        seeds = [PLAYER_TICKET_SEED, player.key().as_ref(), &epoch_id.to_le_bytes()],
         bump
     )]
     pub player_ticket: Account<'info, PlayerTicket>,
-    /// CHECK:
-    pub authority: UncheckedAccount<'info>,
-    pub session_token: Account<'info, SessionToken>,
-    pub ephemeral_signer: Signer<'info>,
-    pub fee_payer: Signer<'info>,
+    #[account(mut)]
+    pub player: Signer<'info>,
     pub system_program: Program<'info, System>,
+    // Token pools only
+    pub mint: Option<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub pool_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub player_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
 }
 
-// ── Phase 5 ──────────────────────────────────────────────────────────────────
+// ── Rollover ─────────────────────────────────────────────────────────────────
 
+#[event_cpi]
 #[derive(Accounts)]
-#[instruction(epoch_id: u64)]
-pub struct RequestWinner<'info> {
+#[instruction(epoch_id: u64, next_epoch_id: u64)]
+pub struct RolloverJackpot<'info> {
     #[account(
         mut,
         seeds = [LOTTERY_POOL_SEED, &epoch_id.to_le_bytes()],
         bump
     )]
     pub lottery_pool: Account<'info, LotteryPool>,
-    /// CHECK:
-    pub authority: UncheckedAccount<'info>,
-    pub session_token: Account<'info, SessionToken>,
-    pub ephemeral_signer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [LOTTERY_POOL_SEED, &next_epoch_id.to_le_bytes()],
+        bump
+    )]
+    pub next_pool: Account<'info, LotteryPool>,
+    pub authority: Signer<'info>,
+    // Token pools only
+    pub mint: Option<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub pool_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub next_pool_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
 }
 
-
-// ── Phase 6 ──────────────────────────────────────────────────────────────────
-
-// Removed #[commit] macro to allow explicit mutability and configurable Magic IDs 
+#[event_cpi]
 #[derive(Accounts)]
 #[instruction(epoch_id: u64)]
-pub struct UndelegatePool<'info> {
+pub struct SweepUnclaimed<'info> {
     #[account(
         mut,
         seeds = [LOTTERY_POOL_SEED, &epoch_id.to_le_bytes()],
         bump
     )]
     pub lottery_pool: Account<'info, LotteryPool>,
-    
-    #[account(mut)] // Payer needs to be mutable for lamport transfers during commit
-    pub payer: Signer<'info>,
-    
-    /// CHECK: Magic context must be mutable for the schedule commit invocation
+    pub authority: Signer<'info>,
+    /// `ForfeitPolicy::NextEpoch` only: the epoch receiving the prizes
     #[account(mut)]
-    pub magic_context: AccountInfo<'info>,
-    
-    /// CHECK: Magic program executable
-    pub magic_program: AccountInfo<'info>,
+    pub next_pool: Option<Account<'info, LotteryPool>>,
+    // Token pools with `ForfeitPolicy::NextEpoch` only
+    pub mint: Option<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub pool_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub next_pool_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
 }
 
-// ── Phase 7 ──────────────────────────────────────────────────────────────────
+// ── Cleanup ──────────────────────────────────────────────────────────────────
 
+#[event_cpi]
 #[derive(Accounts)]
 #[instruction(epoch_id: u64)]
-pub struct ClaimPrize<'info> {
+pub struct ClosePlayerTicket<'info> {
     #[account(
         mut,
         seeds = [LOTTERY_POOL_SEED, &epoch_id.to_le_bytes()],
@@ -768,29 +4041,271 @@ pub struct ClaimPrize<'info> {
     pub lottery_pool: Account<'info, LotteryPool>,
     #[account(
         mut,
-        seeds = [PLAYER_TICKET_SEED, winner.key().as_ref(), &epoch_id.to_le_bytes()],
+        close = owner,
+        seeds = [PLAYER_TICKET_SEED, owner.key().as_ref(), &epoch_id.to_le_bytes()],
         bump
     )]
     pub player_ticket: Account<'info, PlayerTicket>,
+    /// CHECK: Receives the rent; seeds tie it to the ticket
     #[account(mut)]
-    pub winner: Signer<'info>,
-    pub system_program: Program<'info, System>,
+    pub owner: UncheckedAccount<'info>,
+    /// The ticket owner or the pool authority
+    pub closer: Signer<'info>,
 }
 
+#[event_cpi]
 #[derive(Accounts)]
 #[instruction(epoch_id: u64)]
-pub struct WithdrawTaxes<'info> {
+pub struct CloseLotteryPool<'info> {
     #[account(
         mut,
+        close = rent_payer,
         seeds = [LOTTERY_POOL_SEED, &epoch_id.to_le_bytes()],
         bump
     )]
     pub lottery_pool: Account<'info, LotteryPool>,
     #[account(mut)]
     pub authority: Signer<'info>,
+    /// CHECK: Receives the rent; must be whoever paid for the pool
+    #[account(mut, address = lottery_pool.rent_payer)]
+    pub rent_payer: UncheckedAccount<'info>,
+    // Weighted pools only, and required for them
+    #[account(
+        mut,
+        close = authority,
+        seeds = [WEIGHT_TREE_SEED, &epoch_id.to_le_bytes()],
+        bump
+    )]
+    pub weight_tree: Option<AccountLoader<'info, WeightTree>>,
+    // Token pools only
     #[account(mut)]
-    pub treasury: SystemAccount<'info>,
-    pub system_program: Program<'info, System>,
+    pub pool_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+}
+
+// ──────────────────────────────────────────────────────────────────────────────
+// Events
+// ──────────────────────────────────────────────────────────────────────────────
+// Emitted with `emit_cpi!`: the data travels in a self-CPI's instruction data,
+// so indexers get it even when the transaction's logs are truncated.
+
+#[event]
+pub struct ConfigUpdated {
+    pub admin: Pubkey,
+    pub protocol_fee_bps: u16,
+    pub fee_recipient: Pubkey,
+    pub min_base_price: u64,
+    pub max_base_price: u64,
+    pub max_session_lifetime_secs: i64,
+}
+
+#[event]
+pub struct SeriesCreated {
+    pub series_id: u32,
+    pub authority: Pubkey,
+    pub first_epoch_id: u64,
+}
+
+/// From `initialize_lottery`, or `open_next_epoch` with `series_id` set.
+#[event]
+pub struct LotteryInitialized {
+    pub epoch_id: u64,
+    pub pool: Pubkey,
+    pub authority: Pubkey,
+    pub series_id: Option<u32>,
+    pub base_price: u64,
+    pub pricing_curve: PricingCurve,
+    pub draw_authority: Pubkey,
+    pub sale_opens_at: i64,
+    pub sale_closes_at: i64,
+    pub draw_at: i64,
+    pub prize_tiers: Vec<u16>,
+    pub game_mode: GameMode,
+    pub draw_grace_secs: i64,
+}
+
+#[event]
+pub struct PoolDenominated {
+    pub epoch_id: u64,
+    pub mint: Pubkey,
+    pub vault: Pubkey,
+}
+
+#[event]
+pub struct CreditsPurchased {
+    pub epoch_id: u64,
+    pub buyer: Pubkey,
+    pub ticket_amount: u64,
+    pub total_price: u64,
+    pub received: u64, // after Token-2022 transfer fees
+    pub tax: u64,
+}
+
+#[event]
+pub struct WeightTreeCreated {
+    pub epoch_id: u64,
+    pub tree: Pubkey,
+}
+
+#[event]
+pub struct PlayerTicketCreated {
+    pub epoch_id: u64,
+    pub owner: Pubkey,
+}
+
+/// A pool, player ticket or session token handed to an ER validator.
+/// `epoch_id` is None for session tokens.
+#[event]
+pub struct AccountDelegated {
+    pub account: Pubkey,
+    pub epoch_id: Option<u64>,
+    pub validator: Pubkey,
+}
+
+/// Player tickets (or a session token, `epoch_id` None) returned to L1.
+#[event]
+pub struct AccountsUndelegated {
+    pub epoch_id: Option<u64>,
+    pub count: u32,
+}
+
+#[event]
+pub struct SessionIssued {
+    pub authority: Pubkey,
+    pub ephemeral_key: Pubkey,
+    pub valid_until: i64,
+    pub scope: SessionScope,
+}
+
+#[event]
+pub struct SessionRenewed {
+    pub authority: Pubkey,
+    pub ephemeral_key: Pubkey,
+    pub valid_until: i64,
+}
+
+#[event]
+pub struct SessionRevoked {
+    pub authority: Pubkey,
+    pub ephemeral_key: Pubkey,
+}
+
+#[event]
+pub struct SessionClosed {
+    pub authority: Pubkey,
+    pub ephemeral_key: Pubkey,
+}
+
+#[event]
+pub struct TicketIssued {
+    pub epoch_id: u64,
+    pub ticket_id: u64,
+    pub owner: Pubkey,
+    pub ephemeral_key: Pubkey,
+    pub weight: u64,
+    pub count: u64, // ticket ids from ticket_id
+}
+
+#[event]
+pub struct RandomnessRequested {
+    pub epoch_id: u64,
+    pub request_id: Pubkey,
+    pub requested_by: Pubkey,
+}
+
+#[event]
+pub struct WinnerSelected {
+    pub epoch_id: u64,
+    pub winning_ticket_ids: Vec<u64>, // one per drawn tier; NO_WINNER until revealed
+    pub winning_numbers: Vec<u32>,    // pick-number and Pick-N pools only
+    pub prize_pool: u64,
+    pub claim_deadline: i64,
+}
+
+/// Match counts of the Pick-N entries registered, in entry order.
+#[event]
+pub struct TicketRegistered {
+    pub epoch_id: u64,
+    pub owner: Pubkey,
+    pub matches: Vec<u8>,
+}
+
+#[event]
+pub struct TicketRevealed {
+    pub epoch_id: u64,
+    pub ticket_id: u64,
+    pub owner: Pubkey,
+    pub number: u32,
+}
+
+/// ER state of the pool written back to L1, with `player_tickets` tickets.
+#[event]
+pub struct PoolCommitted {
+    pub epoch_id: u64,
+    pub ticket_count: u64,
+    pub player_tickets: u32,
+    pub undelegated: bool,
+}
+
+#[event]
+pub struct PrizeClaimed {
+    pub epoch_id: u64,
+    pub tier: u8,
+    pub ticket_id: u64, // first of the winning entries
+    pub entries: u32,   // more than one only in Pick-N pools
+    pub winner: Pubkey,
+    pub payout: u64,
+    pub tax: u64,
+}
+
+#[event]
+pub struct TaxesWithdrawn {
+    pub epoch_id: u64,
+    pub treasury: Pubkey,
+    pub amount: u64,
+    pub fee_recipient: Pubkey,
+    pub protocol_fee: u64,
+}
+
+/// `timed_out` when a refund claim cancelled an epoch never drawn in time.
+#[event]
+pub struct EpochCancelled {
+    pub epoch_id: u64,
+    pub timed_out: bool,
+}
+
+#[event]
+pub struct RefundClaimed {
+    pub epoch_id: u64,
+    pub player: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct JackpotRolledOver {
+    pub epoch_id: u64,
+    pub next_epoch_id: u64,
+    pub amount: u64,
+}
+
+#[event]
+pub struct UnclaimedSwept {
+    pub epoch_id: u64,
+    pub amount: u64,
+    pub policy: ForfeitPolicy,
+    pub next_epoch_id: Option<u64>,
+}
+
+#[event]
+pub struct PlayerTicketClosed {
+    pub epoch_id: u64,
+    pub owner: Pubkey,
+}
+
+#[event]
+pub struct LotteryPoolClosed {
+    pub epoch_id: u64,
+    pub rent_payer: Pubkey,
 }
 
 // ──────────────────────────────────────────────────────────────────────────────
@@ -811,7 +4326,7 @@ pub enum LottryError {
     EpochMismatch,
     #[msg("Expiry timestamp must be in the future.")]
     InvalidExpiry,
-    #[msg("Invalid tax rate (basis points must be <= 10000).")]
+    #[msg("Invalid fee rate (basis points must be <= 10000).")]
     InvalidTaxRate,
     #[msg("Ticket amount must be greater than zero.")]
     InvalidTicketAmount,
@@ -841,4 +4356,543 @@ pub enum LottryError {
     InvalidValidator,
     #[msg("Account must be writable for commit/undelegate.")]
     AccountNotWritable,
+    #[msg("Randomness already requested for this epoch.")]
+    RandomnessAlreadyRequested,
+    #[msg("Randomness was not requested for this epoch.")]
+    RandomnessNotRequested,
+    #[msg("Winner already selected for this epoch.")]
+    WinnerAlreadySet,
+    #[msg("Player has reached the entry limit for this epoch (MAX_TICKETS_PER_PLAYER entries; the last must spend every credit left).")]
+    TicketLimitReached,
+    #[msg("Only the pool authority or crank may draw through a session key.")]
+    UnauthorizedDrawer,
+    #[msg("Too early — the permissionless draw opens draw_grace_secs after draw_at.")]
+    DrawTooEarly,
+    #[msg("Sale window must open before it closes, and close no later than the draw.")]
+    InvalidSaleWindow,
+    #[msg("Ticket sales have not opened yet.")]
+    SaleNotOpen,
+    #[msg("Ticket sales are closed.")]
+    SaleClosed,
+    #[msg("Scheduled draw time has not been reached.")]
+    DrawTimeNotReached,
+    #[msg("Epoch has been cancelled.")]
+    EpochCancelled,
+    #[msg("Refunds are only available for cancelled or abandoned epochs.")]
+    RefundNotAvailable,
+    #[msg("Nothing to refund for this ticket.")]
+    NothingToRefund,
+    #[msg("Pool lamports do not cover the tracked prize pool and fees.")]
+    AccountingMismatch,
+    #[msg("Invalid pricing curve parameters.")]
+    InvalidPricingCurve,
+    #[msg("Total price exceeds the buyer's limit.")]
+    PriceExceedsLimit,
+    #[msg("Pool denomination can only be set once, before the pool holds any stake.")]
+    DenominationLocked,
+    #[msg("Token pools require the mint, vault, user token account and token program.")]
+    MissingTokenAccounts,
+    #[msg("Mint does not match the pool's denomination.")]
+    InvalidMint,
+    #[msg("Token account does not match the pool vault, mint or owner.")]
+    InvalidTokenAccount,
+    #[msg("Prize table must have 1-8 non-zero tiers summing to 10000 bps.")]
+    InvalidPrizeTable,
+    #[msg("No winner was drawn for this prize tier.")]
+    InvalidPrizeTier,
+    #[msg("Prize tier already claimed.")]
+    PrizeAlreadyClaimed,
+    #[msg("Only epochs that ended without selling credits, or drawn epochs with leftover stake, can roll over.")]
+    RolloverNotAvailable,
+    #[msg("Rollover target must be a later, undrawn epoch with the same authority and denomination.")]
+    InvalidRolloverTarget,
+    #[msg("Epoch stake has been rolled over into a later epoch.")]
+    JackpotRolledOver,
+    #[msg("Claim window must be positive.")]
+    InvalidClaimWindow,
+    #[msg("The claim deadline for this epoch has passed.")]
+    ClaimDeadlinePassed,
+    #[msg("Winners can still claim — sweep after the claim deadline.")]
+    ClaimWindowOpen,
+    #[msg("No unclaimed prizes to sweep.")]
+    NothingToSweep,
+    #[msg("Caller is not allowed to create lotteries.")]
+    UnauthorizedCreator,
+    #[msg("Base price is outside the configured bounds.")]
+    PriceOutOfBounds,
+    #[msg("Minimum base price must not exceed the maximum.")]
+    InvalidPriceBounds,
+    #[msg("Too many allowed validators or lottery creators.")]
+    ConfigListTooLong,
+    #[msg("Series id must be non-zero.")]
+    InvalidSeries,
+    #[msg("Series has used up its epoch id range.")]
+    SeriesExhausted,
+    #[msg("Session token has been revoked.")]
+    SessionRevoked,
+    #[msg("Session token is still valid — revoke it or wait for expiry.")]
+    SessionStillValid,
+    #[msg("Session scope must allow at least one known instruction.")]
+    InvalidSessionScope,
+    #[msg("Session is not allowed to act on this instruction, epoch or pool.")]
+    SessionScopeViolation,
+    #[msg("Session has spent its credit allowance.")]
+    SessionCreditLimitReached,
+    #[msg("Maximum session lifetime must be positive.")]
+    InvalidSessionLifetime,
+    #[msg("Session expiry is further out than the configured maximum lifetime.")]
+    SessionLifetimeExceeded,
+    #[msg("Account is not a PlayerTicket of this program.")]
+    InvalidPlayerTicket,
+    #[msg("Ticket may still be owed a prize or refund.")]
+    TicketNotSettled,
+    #[msg("Pool still holds prizes, refunds or fees, or has not finished.")]
+    PoolNotSettled,
+    #[msg("Close every PlayerTicket of the epoch first.")]
+    TicketsStillOpen,
+    #[msg("Not available in this pool's game mode, or invalid game mode settings.")]
+    InvalidGameMode,
+    #[msg("Reveal window is still open.")]
+    RevealWindowOpen,
+    #[msg("Reveal window has closed.")]
+    RevealWindowClosed,
+    #[msg("Revealed number does not match the ticket's commitment.")]
+    InvalidReveal,
+    #[msg("Ticket entry already revealed.")]
+    TicketAlreadyRevealed,
+    #[msg("Ticket data is not a valid pick for this pool.")]
+    InvalidTicketData,
+    #[msg("Every entry of this ticket is already registered.")]
+    TicketAlreadyRegistered,
+    #[msg("Weighted pools need their WeightTree account.")]
+    MissingWeightTree,
+    #[msg("WeightTree has no room for more entries.")]
+    WeightTreeFull,
+    #[msg("Epoch ids from 1 << 32 up are reserved for series.")]
+    EpochIdReserved,
+    #[msg("Draw grace period must be non-negative and end before refunds open.")]
+    InvalidDrawGrace,
+}
+
+// ──────────────────────────────────────────────────────────────────────────────
+// Tests
+// ──────────────────────────────────────────────────────────────────────────────
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn weight_tree(weights: &[u64]) -> Box<WeightTree> {
+        let mut tree: Box<WeightTree> = Box::new(bytemuck::Zeroable::zeroed());
+        for (id, weight) in weights.iter().enumerate() {
+            tree.add(id as u64, *weight).unwrap();
+        }
+        tree
+    }
+
+    #[test]
+    fn weight_tree_finds_each_ticket_across_its_slice() {
+        let weights = [3, 1, 4, 1, 5, 9, 2, 6];
+        let tree = weight_tree(&weights);
+        assert_eq!(tree.total_weight, weights.iter().sum::<u64>());
+
+        let mut start = 0;
+        for (id, weight) in weights.iter().enumerate() {
+            assert_eq!(tree.weight_of(id as u64), *weight);
+            // First and last unit of the slice, and the first of the next one
+            assert_eq!(tree.find(start), id as u64);
+            assert_eq!(tree.find(start + weight - 1), id as u64);
+            start += weight;
+            if id + 1 < weights.len() {
+                assert_eq!(tree.find(start), id as u64 + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn weight_tree_skips_removed_weight() {
+        let mut tree = weight_tree(&[2, 3, 4]);
+        tree.remove(1, 3).unwrap();
+        assert_eq!(tree.total_weight, 6);
+        assert_eq!(tree.weight_of(1), 0);
+        assert_eq!(tree.find(1), 0);
+        assert_eq!(tree.find(2), 2);
+        assert_eq!(tree.find(5), 2);
+    }
+
+    #[test]
+    fn weight_tree_rejects_entries_past_capacity() {
+        let mut tree = weight_tree(&[]);
+        tree.add(WEIGHT_TREE_CAPACITY as u64 - 1, 1).unwrap();
+        assert!(tree.add(WEIGHT_TREE_CAPACITY as u64, 1).is_err());
+        assert_eq!(tree.find(0), WEIGHT_TREE_CAPACITY as u64 - 1);
+    }
+
+    #[test]
+    fn weight_tree_draws_distinct_winners_and_restores_weights() {
+        let weights = [1, 50, 1, 1, 20, 1, 1, 30];
+        for seed in 0..32u8 {
+            let mut tree = weight_tree(&weights);
+            let nodes = tree.nodes;
+            let winners = tree.draw_winners(&[seed; 32], 5).unwrap();
+
+            assert_eq!(winners.len(), 5);
+            for (i, winner) in winners.iter().enumerate() {
+                assert!((*winner as usize) < weights.len());
+                assert!(!winners[..i].contains(winner));
+            }
+            assert_eq!(tree.nodes, nodes);
+            assert_eq!(tree.total_weight, weights.iter().sum::<u64>());
+        }
+    }
+
+    #[test]
+    fn weight_tree_draws_every_ticket_when_tiers_cover_them() {
+        let mut tree = weight_tree(&[7, 1, 1]);
+        let mut winners = tree.draw_winners(&[9; 32], 3).unwrap();
+        winners.sort();
+        assert_eq!(winners, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn weight_tree_draw_needs_weight() {
+        let mut tree = weight_tree(&[]);
+        assert!(tree.draw_winners(&[0; 32], 1).is_err());
+    }
+
+    fn lottery_params() -> LotteryParams {
+        LotteryParams {
+            base_price: 1_000,
+            pricing_curve: PricingCurve::Flat,
+            purchase_fee_bps: 500,
+            payout_fee_bps: 0,
+            draw_authority: Pubkey::default(),
+            sale_opens_at: 0,
+            sale_closes_at: 100,
+            draw_at: 100,
+            prize_tiers: vec![10_000],
+            claim_window_secs: 60,
+            forfeit_policy: ForfeitPolicy::Treasury,
+            game_mode: GameMode::RandomTicket,
+            reveal_window_secs: 0,
+            draw_grace_secs: 0,
+        }
+    }
+
+    #[test]
+    fn draw_grace_ends_before_refunds_open() {
+        for (draw_grace_secs, valid) in [
+            (0, true),
+            (REFUND_TIMEOUT_SECS - 1, true),
+            (-1, false),
+            (REFUND_TIMEOUT_SECS, false),
+        ] {
+            let params = LotteryParams {
+                draw_grace_secs,
+                ..lottery_params()
+            };
+            assert_eq!(params.validate().is_ok(), valid, "{draw_grace_secs}");
+        }
+    }
+
+    /// A pool freshly created from `params`, with a prize pool of 10_000.
+    fn lottery_pool(params: LotteryParams) -> LotteryPool {
+        params.validate().unwrap();
+        let mut pool =
+            LotteryPool::try_deserialize_unchecked(&mut &[0u8; LotteryPool::LEN][..]).unwrap();
+        pool.init(Pubkey::default(), Pubkey::default(), 1, &params, 0);
+        pool.total_staked_sol = 10_000;
+        pool.prize_pool_sol = 10_000;
+        pool
+    }
+
+    /// A drawn Pick-N pool, as `consume_randomness` leaves it.
+    fn pick_n_pool(picks: u8, prize_tiers: Vec<u16>, winning_numbers: Vec<u32>) -> LotteryPool {
+        let mut pool = lottery_pool(LotteryParams {
+            game_mode: GameMode::PickN {
+                picks,
+                max_number: 10,
+            },
+            reveal_window_secs: 60,
+            prize_tiers,
+            ..lottery_params()
+        });
+        pool.winning_numbers = winning_numbers;
+        pool.tier_winners = vec![0; pool.prize_tiers.len()];
+        pool.tier_claims = vec![0; pool.prize_tiers.len()];
+        pool
+    }
+
+    fn pick_n_data(numbers: &[u8]) -> [u8; 32] {
+        let mut data = [0u8; 32];
+        data[..numbers.len()].copy_from_slice(numbers);
+        data
+    }
+
+    #[test]
+    fn pick_n_ticket_data_takes_distinct_in_range_numbers() {
+        let mode = GameMode::PickN {
+            picks: 3,
+            max_number: 10,
+        };
+        assert!(mode.require_valid_ticket_data(&pick_n_data(&[10, 1, 5])).is_ok());
+        for numbers in [[1, 1, 5], [0, 1, 5], [1, 5, 11]] {
+            assert_eq!(
+                mode.require_valid_ticket_data(&pick_n_data(&numbers)).unwrap_err(),
+                LottryError::InvalidTicketData.into(),
+                "{numbers:?}"
+            );
+        }
+        let mut padded = pick_n_data(&[1, 2, 3]);
+        padded[31] = 1;
+        assert!(mode.require_valid_ticket_data(&padded).is_err());
+        // Other modes treat the data as opaque
+        assert!(GameMode::RandomTicket.require_valid_ticket_data(&[0xff; 32]).is_ok());
+    }
+
+    #[test]
+    fn pick_n_registration_counts_matches_into_tiers() {
+        // Two tiers: three and two matches; a single match wins nothing
+        let mut pool = pick_n_pool(3, vec![7_000, 3_000], vec![2, 4, 6]);
+        for (numbers, matches) in [
+            ([6, 2, 4], 3),
+            ([2, 4, 9], 2),
+            ([4, 1, 6], 2),
+            ([2, 3, 5], 1),
+            ([1, 3, 5], 0),
+        ] {
+            assert_eq!(
+                pool.register_entry(&pick_n_data(&numbers), 3).unwrap(),
+                matches
+            );
+        }
+        assert_eq!(pool.tier_winners, vec![1, 2]);
+    }
+
+    #[test]
+    fn pick_n_winners_split_their_tier() {
+        let mut pool = pick_n_pool(2, vec![7_000, 3_000], vec![4, 5]);
+        for numbers in [[4, 5], [5, 4], [4, 6], [7, 5], [4, 8]] {
+            pool.register_entry(&pick_n_data(&numbers), 2).unwrap();
+        }
+        assert_eq!(pool.winner_share(0).unwrap(), 3_500);
+        assert_eq!(pool.winner_share(1).unwrap(), 1_000);
+        assert_eq!(pool.unclaimed_prizes().unwrap(), 10_000);
+        assert_eq!(pool.winner_share(2).unwrap_err(), LottryError::InvalidPrizeTier.into());
+
+        // A tier nobody matched has no share to split
+        let pool = pick_n_pool(2, vec![7_000, 3_000], vec![4, 5]);
+        assert_eq!(pool.winner_share(0).unwrap_err(), LottryError::NotWinner.into());
+        assert_eq!(pool.unclaimed_prizes().unwrap(), 0);
+    }
+
+    #[test]
+    fn pick_n_claim_pays_each_matching_entry_once() {
+        let mut pool = pick_n_pool(2, vec![7_000, 3_000], vec![4, 5]);
+        let mut ticket = PlayerTicket::try_deserialize_unchecked(
+            &mut &[0u8; PlayerTicket::LEN][..],
+        )
+        .unwrap();
+        for (ticket_id, numbers) in [[1, 2], [4, 5], [4, 9], [5, 4]].iter().enumerate() {
+            let data = pick_n_data(numbers);
+            let matches = pool.register_entry(&data, 2).unwrap();
+            ticket.entries.push(TicketEntry {
+                ticket_id: ticket_id as u64,
+                ticket_data: data,
+                revealed: None,
+                matches: Some(matches),
+                weight: 1,
+                count: 1,
+            });
+        }
+        // Another player's entry shares the top tier
+        pool.register_entry(&pick_n_data(&[5, 4]), 2).unwrap();
+        assert_eq!(pool.tier_winners, vec![3, 1]);
+
+        // Both of the ticket's top-tier entries are paid in one claim
+        let share = pool.winner_share(0).unwrap();
+        assert_eq!(
+            pool.claim_pick_n_tier(&mut ticket, 0, 2).unwrap(),
+            (share * 2, 1, 2)
+        );
+        assert_eq!(pool.tier_claims, vec![2, 0]);
+        assert!(ticket.is_tier_claimed(0));
+        assert_eq!(
+            pool.claim_pick_n_tier(&mut ticket, 0, 2).unwrap_err(),
+            LottryError::PrizeAlreadyClaimed.into()
+        );
+
+        assert_eq!(
+            pool.claim_pick_n_tier(&mut ticket, 1, 2).unwrap(),
+            (3_000, 2, 1)
+        );
+        assert_eq!(pool.tier_claims, vec![2, 1]);
+        // Only the other player's top-tier split is still owed
+        assert_eq!(pool.unclaimed_prizes().unwrap(), share);
+
+        for tier in [2, 3] {
+            assert_eq!(
+                pool.claim_pick_n_tier(&mut ticket, tier, 2).unwrap_err(),
+                LottryError::InvalidPrizeTier.into()
+            );
+        }
+        ticket.entries.retain(|e| e.matches == Some(0));
+        ticket.claimed_tiers = 0;
+        assert_eq!(
+            pool.claim_pick_n_tier(&mut ticket, 0, 2).unwrap_err(),
+            LottryError::NotWinner.into()
+        );
+    }
+
+    #[test]
+    fn unwon_prizes_cover_the_tiers_nobody_holds() {
+        // Three tiers but only two tickets: the last tier is never drawn
+        let mut pool = lottery_pool(LotteryParams {
+            prize_tiers: vec![5_000, 3_000, 2_000],
+            ..lottery_params()
+        });
+        pool.winning_ticket_ids = vec![1, 0];
+        assert_eq!(pool.unwon_prizes().unwrap(), 2_000);
+
+        // A pick-number tier stays unwon until an entry reveals its number
+        pool.game_mode = GameMode::PickNumber { max_number: 3 };
+        pool.winning_ticket_ids = vec![1, NO_WINNER, 0];
+        assert_eq!(pool.unwon_prizes().unwrap(), 3_000);
+
+        let mut pool = pick_n_pool(3, vec![6_000, 3_000, 1_000], vec![1, 2, 3]);
+        pool.register_entry(&pick_n_data(&[1, 2, 4]), 3).unwrap();
+        pool.register_entry(&pick_n_data(&[1, 5, 6]), 3).unwrap();
+        assert_eq!(pool.tier_winners, vec![0, 1, 1]);
+        assert_eq!(pool.unwon_prizes().unwrap(), 6_000);
+    }
+
+    #[test]
+    fn ticket_holds_every_id_of_its_ranges() {
+        let mut ticket = PlayerTicket::try_deserialize_unchecked(
+            &mut &[0u8; PlayerTicket::LEN][..],
+        )
+        .unwrap();
+        for (ticket_id, count) in [(2, 3), (9, 1)] {
+            ticket.entries.push(TicketEntry {
+                ticket_id,
+                ticket_data: [0; 32],
+                revealed: None,
+                matches: None,
+                weight: count,
+                count,
+            });
+        }
+        let held: Vec<u64> = (0..12).filter(|id| ticket.holds(*id)).collect();
+        assert_eq!(held, vec![2, 3, 4, 9]);
+    }
+
+    /// Price of credit `i` straight from the `PricingCurve` docs.
+    fn naive_price(curve: PricingCurve, base_price: u64, i: u64) -> f64 {
+        let (base, i) = (base_price as f64, i as f64);
+        match curve {
+            PricingCurve::Flat => base,
+            PricingCurve::Linear { slope } => base + slope as f64 * i,
+            PricingCurve::Exponential { growth_bps } => {
+                base * (1.0 + growth_bps as f64 / 10_000.0).powf(i)
+            }
+            PricingCurve::Stepped {
+                step_size,
+                increment,
+            } => base + (increment * (i as u64 / step_size)) as f64,
+        }
+    }
+
+    fn naive_cost(curve: PricingCurve, base_price: u64, from: u64, to: u64) -> f64 {
+        (from..to).map(|i| naive_price(curve, base_price, i)).sum()
+    }
+
+    /// Cost of credits `from..to` the way `quote_credits` prices a batch.
+    fn batch_cost(curve: PricingCurve, base_price: u64, from: u64, to: u64) -> u128 {
+        curve.cumulative_cost(base_price, to).unwrap()
+            - curve.cumulative_cost(base_price, from).unwrap()
+    }
+
+    const EXACT_CURVES: [PricingCurve; 5] = [
+        PricingCurve::Flat,
+        PricingCurve::Linear { slope: 7 },
+        PricingCurve::Stepped {
+            step_size: 1,
+            increment: 3,
+        },
+        PricingCurve::Stepped {
+            step_size: 5,
+            increment: 250,
+        },
+        PricingCurve::Exponential { growth_bps: 0 },
+    ];
+
+    #[test]
+    fn exact_curves_match_the_per_credit_sum() {
+        for curve in EXACT_CURVES {
+            for to in 0..=60 {
+                for from in 0..=to {
+                    let naive = naive_cost(curve, 1_000, from, to);
+                    assert_eq!(
+                        batch_cost(curve, 1_000, from, to) as f64,
+                        naive,
+                        "{curve:?} credits {from}..{to}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn exponential_curve_tracks_the_per_credit_sum() {
+        for growth_bps in [1, 500, 2_500, 10_000] {
+            let curve = PricingCurve::Exponential { growth_bps };
+            for to in 0..=40 {
+                for from in 0..=to {
+                    let naive = naive_cost(curve, 1_000_000, from, to);
+                    let cost = batch_cost(curve, 1_000_000, from, to) as f64;
+                    // Fixed-point rounding of f^n, scaled up by 1 / g on shallow curves
+                    assert!(
+                        (cost - naive).abs() <= 2.0 + naive * 1e-5,
+                        "{curve:?} credits {from}..{to}: {cost} vs {naive}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn splitting_a_batch_keeps_its_cost() {
+        let curves = EXACT_CURVES
+            .into_iter()
+            .chain([PricingCurve::Exponential { growth_bps: 500 }]);
+        for curve in curves {
+            let whole = curve.cumulative_cost(1_000, 30).unwrap();
+            // One purchase, credit-by-credit, and uneven chunks
+            for chunks in [vec![30], vec![1; 30], vec![4, 11, 1, 9, 5]] {
+                let mut sold = 0;
+                let mut paid = 0;
+                for chunk in chunks {
+                    paid += batch_cost(curve, 1_000, sold, sold + chunk);
+                    sold += chunk;
+                }
+                assert_eq!(paid, whole, "{curve:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn curve_overflow_is_an_error() {
+        let linear = PricingCurve::Linear { slope: u64::MAX };
+        assert!(linear.cumulative_cost(u64::MAX, u64::MAX).is_err());
+        let exponential = PricingCurve::Exponential { growth_bps: 10_000 };
+        assert!(exponential.cumulative_cost(1, 1_000).is_err());
+    }
+
+    #[test]
+    fn fixed_pow_raises_fixed_point_factors() {
+        assert_eq!(fixed_pow(CURVE_SCALE * 2, 0).unwrap(), CURVE_SCALE);
+        assert_eq!(fixed_pow(CURVE_SCALE, 1_000_000).unwrap(), CURVE_SCALE);
+        assert_eq!(fixed_pow(CURVE_SCALE * 2, 10).unwrap(), CURVE_SCALE * 1024);
+        assert_eq!(fixed_pow(CURVE_SCALE / 2, 3).unwrap(), CURVE_SCALE / 8);
+        assert!(fixed_pow(CURVE_SCALE * 2, 200).is_err());
+    }
 }
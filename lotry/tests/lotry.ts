@@ -4,8 +4,21 @@ import { MAGIC_CONTEXT_ID, MAGIC_PROGRAM_ID } from "@magicblock-labs/ephemeral-r
 import { Lotry } from "../target/types/lotry";
 import { expect } from "chai";
 import { PublicKey, Keypair } from "@solana/web3.js";
+import {
+  ExtensionType,
+  TOKEN_2022_PROGRAM_ID,
+  TOKEN_PROGRAM_ID,
+  createInitializeMintInstruction,
+  createInitializeTransferFeeConfigInstruction,
+  createMint,
+  getAccount,
+  getMintLen,
+  getOrCreateAssociatedTokenAccount,
+  harvestWithheldTokensToMint,
+  mintTo,
+} from "@solana/spl-token";
 import BN from "bn.js";
-import { randomBytes } from "crypto";
+import { createHash, randomBytes } from "crypto";
 
 describe("lotry", () => {
   // Connections: use MagicBlock's devnet RPC for both ER + L1
@@ -22,38 +35,79 @@ describe("lotry", () => {
   const l1Program = new Program(anchor.workspace.Lotry.idl, l1Provider) as Program<Lotry>;
   const erProgram = new Program(anchor.workspace.Lotry.idl, erProvider) as Program<Lotry>;
 
-  // Hardcode an epoch ID for predictability in tests (bump if rerunning on devnet)
-  const epochId = new BN(1102); // bump if Devnet complains about reused PDAs
+  // Epochs come from a persistent series, so reruns just open the next epoch
+  // (bump the series id if the template below changes)
+  const seriesId = 1104;
   const sessionKey = Keypair.generate();
   const validUntil = Math.floor(Date.now() / 1000) + 3600; // 1 hour from now
 
-  const [poolPda] = PublicKey.findProgramAddressSync(
-    [Buffer.from("lottery_pool"), epochId.toArrayLike(Buffer, "le", 8)],
+  const [seriesPda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("series"), new BN(seriesId).toArrayLike(Buffer, "le", 4)],
     l1Program.programId
   );
+  const poolPdaFor = (epoch: BN) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("lottery_pool"), epoch.toArrayLike(Buffer, "le", 8)],
+      l1Program.programId
+    )[0];
+  // Assigned in Phase 1 once the series opens its next epoch
+  let epochId: BN;
+  let poolPda: PublicKey;
+  let playerTicketPda: PublicKey;
+  // SessionScope.instructions bits
+  const SESSION_SCOPE_BUY_TICKET = 1 << 0;
+  const SESSION_SCOPE_REQUEST_RANDOMNESS = 1 << 1;
+  const sessionCreditLimit = 2;
   const [sessionPda] = PublicKey.findProgramAddressSync(
     [Buffer.from("session"), wallet.publicKey.toBuffer(), sessionKey.publicKey.toBuffer()],
     l1Program.programId
   );
   const basePrice = new BN(1_000);
-  const curveMultiplier = new BN(1);
-  const taxRateBps = 500; // 5%
+  const curveSlope = new BN(1);
+  const pricingCurve = { linear: { slope: curveSlope } };
+  const purchaseFeeBps = 500; // 5% of every credit purchase
+  const payoutFeeBps = 0; // prize is not taxed a second time
+  // 60% / 25% / 15% across three winners; with two tickets only two tiers are drawn
+  const prizeTiers = [6000, 2500, 1500];
+  // Winners have a day to claim; anything left afterwards goes to the treasury
+  const claimWindowSecs = new BN(24 * 60 * 60);
+  const forfeitPolicy = { treasury: {} };
+  // One random ticket per tier, so there is nothing to reveal after the draw
+  const gameMode = { randomTicket: {} };
+  const revealWindowSecs = new BN(0);
+  // Only the wallet (authority and crank) may draw in the first 10 minutes after drawAt
+  const drawGraceSecs = new BN(600);
+  // Sales run for long enough to get through Phases 1-7; the draw follows immediately
+  const saleDurationSecs = new BN(240);
+  const drawDelaySecs = new BN(0);
+  let drawAt: BN;
 
   const treasury = Keypair.generate();
+  const feeRecipient = Keypair.generate();
+  const protocolFeeBps = 1000; // protocol keeps 10% of each pool's taxes
+  const maxSessionLifetimeSecs = new BN(2 * 3600);
 
-  const [playerTicketPda] = PublicKey.findProgramAddressSync(
-    [Buffer.from("player_ticket"), wallet.publicKey.toBuffer(), epochId.toArrayLike(Buffer, "le", 8)],
+  const [configPda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("config")],
     l1Program.programId
   );
 
+  // MagicBlock VRF oracle queue serving the Ephemeral Rollup
+  const EPHEMERAL_ORACLE_QUEUE = new PublicKey("5hBR571xnXppuCPveTrctfTU7tJLSN94nq7kv7FRK5Tc");
+
+
   let expectedTotalStaked = new BN(0);
   let expectedTaxTreasury = new BN(0);
   let expectedTicketBalance = new BN(0);
 
-  const calcPurchase = (totalStaked: BN, ticketAmount: BN) => {
-    const currentPrice = basePrice.add(curveMultiplier.mul(totalStaked));
-    const totalPrice = currentPrice.add(curveMultiplier.mul(ticketAmount));
-    const tax = totalPrice.muln(taxRateBps).divn(10_000);
+  let expectedCreditsSold = new BN(0);
+
+  // Linear curve: credit i costs basePrice + slope * i, summed over the batch
+  const calcPurchase = (creditsSold: BN, ticketAmount: BN) => {
+    const currentPrice = basePrice.add(curveSlope.mul(creditsSold));
+    const steps = creditsSold.mul(ticketAmount).add(ticketAmount.mul(ticketAmount.subn(1)).divn(2));
+    const totalPrice = basePrice.mul(ticketAmount).add(curveSlope.mul(steps));
+    const tax = totalPrice.muln(purchaseFeeBps).divn(10_000);
     const net = totalPrice.sub(tax);
     return { currentPrice, totalPrice, tax, net };
   };
@@ -71,26 +125,457 @@ describe("lotry", () => {
     throw new Error("Retry failed");
   };
 
+  const fundIfEmpty = async (pubkey: PublicKey) => {
+    const existingBalance = await l1Connection.getBalance(pubkey);
+    if (existingBalance > 0) return;
+    const fundIx = anchor.web3.SystemProgram.transfer({
+      fromPubkey: wallet.publicKey,
+      toPubkey: pubkey,
+      lamports: 1_000_000,
+    });
+    const fundTx = new anchor.web3.Transaction().add(fundIx);
+    fundTx.recentBlockhash = (await l1Connection.getLatestBlockhash()).blockhash;
+    fundTx.feePayer = wallet.publicKey;
+    await anchor.web3.sendAndConfirmTransaction(l1Connection, fundTx, [(wallet as any).payer ?? wallet], {
+      commitment: "confirmed",
+    });
+  };
+
+  // Events are emitted via self-CPI: skip the 8-byte event-CPI tag of each
+  // inner instruction into this program and decode the rest
+  const cpiEvents = async (signature: string) => {
+    const tx = await l1Connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const keys = tx.transaction.message.getAccountKeys({ accountKeysFromLookups: tx.meta.loadedAddresses });
+    return (tx.meta.innerInstructions ?? [])
+      .flatMap(inner => inner.instructions)
+      .filter(ix => keys.get(ix.programIdIndex).equals(l1Program.programId))
+      .map(ix => l1Program.coder.events.decode(
+        Buffer.from(anchor.utils.bytes.bs58.decode(ix.data)).subarray(8).toString("base64")
+      ))
+      .filter(event => event !== null);
+  };
+
+  // ── Standalone epochs ──────────────────────────────────────────────────
+  // The suites after the main flow each run their own hand-picked epoch
+  // (below 1 << 32, outside every series' range) through the ER and back.
+
+  const DELEGATION_PROGRAM_ID = new PublicKey("DELeGGvXpWV2fqJUhqcF5ZSYMS4JTLjteaAMARRSaeSh");
+  const TEE_VALIDATOR = new PublicKey("MAS1Dt9qreoRMQ14YQuhg8UTZMMzDdKhmkZMECCzk57");
+
+  const playerTicketPdaFor = (epoch: BN) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("player_ticket"), wallet.publicKey.toBuffer(), epoch.toArrayLike(Buffer, "le", 8)],
+      l1Program.programId
+    )[0];
+  const weightTreePdaFor = (epoch: BN) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("weight_tree"), epoch.toArrayLike(Buffer, "le", 8)],
+      l1Program.programId
+    )[0];
+  // Buffer and delegation-program PDAs `delegate_*` needs for `account`
+  const delegationPdas = (account: PublicKey) => ({
+    buffer: PublicKey.findProgramAddressSync([Buffer.from("buffer"), account.toBuffer()], l1Program.programId)[0],
+    record: PublicKey.findProgramAddressSync([Buffer.from("delegation"), account.toBuffer()], DELEGATION_PROGRAM_ID)[0],
+    metadata: PublicKey.findProgramAddressSync([Buffer.from("delegation-metadata"), account.toBuffer()], DELEGATION_PROGRAM_ID)[0],
+  });
+
+  const expectError = async (fn: () => Promise<unknown>, code: string) => {
+    try {
+      await fn();
+    } catch (e: any) {
+      expect(e.error?.errorCode?.code).to.equal(code);
+      return;
+    }
+    expect.fail(`expected ${code}`);
+  };
+
+  const waitUntil = async (unixSecs: number) => {
+    const waitMs = (unixSecs + 2) * 1000 - Date.now();
+    if (waitMs > 0) {
+      console.log(`Waiting ${Math.ceil(waitMs / 1000)}s...`);
+      await new Promise(r => setTimeout(r, waitMs));
+    }
+  };
+
+  // Create a single-tier, flat-priced epoch with the wallet's PlayerTicket;
+  // `overrides` replaces any LotteryParams field
+  const initStandaloneEpoch = async (
+    overrides: Record<string, any> = {},
+    saleSecs = 180,
+    epoch = new BN(randomBytes(4).readUInt32LE(0) || 1)
+  ) => {
+    const now = Math.floor(Date.now() / 1000);
+    const params = {
+      basePrice,
+      pricingCurve: { flat: {} },
+      purchaseFeeBps,
+      payoutFeeBps,
+      drawAuthority: wallet.publicKey,
+      saleOpensAt: new BN(now - 60),
+      saleClosesAt: new BN(now + saleSecs),
+      drawAt: new BN(now + saleSecs),
+      prizeTiers: [10_000],
+      claimWindowSecs,
+      forfeitPolicy,
+      gameMode: { randomTicket: {} },
+      revealWindowSecs: new BN(0),
+      drawGraceSecs,
+      ...overrides,
+    };
+    await withRetry(() => l1Program.methods
+      .initializeLottery(epoch, params)
+      .accounts({ authority: wallet.publicKey } as any)
+      .rpc());
+    await withRetry(() => l1Program.methods
+      .initPlayerTicket(epoch)
+      .accounts({
+        playerTicket: playerTicketPdaFor(epoch),
+        lotteryPool: poolPdaFor(epoch),
+        authority: wallet.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      } as any)
+      .rpc());
+    console.log("Standalone epoch", epoch.toString(), "initialized");
+    return { epoch, pool: poolPdaFor(epoch), ticket: playerTicketPdaFor(epoch), params };
+  };
+
+  // Delegate the pool, a weighted epoch's tree and the wallet's ticket to the TEE validator
+  const delegateEpoch = async (epoch: BN, weighted = false) => {
+    const pool = poolPdaFor(epoch);
+    const ticket = playerTicketPdaFor(epoch);
+    const poolPdas = delegationPdas(pool);
+    await withRetry(() => l1Program.methods
+      .delegateLottery(epoch)
+      .accounts({
+        authority: wallet.publicKey,
+        validator: TEE_VALIDATOR,
+        bufferLotteryPool: poolPdas.buffer,
+        delegationRecordLotteryPool: poolPdas.record,
+        delegationMetadataLotteryPool: poolPdas.metadata,
+        delegationProgram: DELEGATION_PROGRAM_ID,
+        ownerProgram: l1Program.programId,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      } as any)
+      .rpc());
+
+    if (weighted) {
+      const tree = weightTreePdaFor(epoch);
+      const treePdas = delegationPdas(tree);
+      await withRetry(() => l1Program.methods
+        .delegateWeightTree(epoch)
+        .accounts({
+          weightTree: tree,
+          lotteryPool: pool,
+          authority: wallet.publicKey,
+          validator: TEE_VALIDATOR,
+          bufferWeightTree: treePdas.buffer,
+          delegationRecord: treePdas.record,
+          delegationMetadata: treePdas.metadata,
+          delegationProgram: DELEGATION_PROGRAM_ID,
+          ownerProgram: l1Program.programId,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        } as any)
+        .rpc());
+    }
+
+    const ticketPdas = delegationPdas(ticket);
+    await withRetry(() => l1Program.methods
+      .delegatePlayerTicket(epoch)
+      .accounts({
+        playerTicket: ticket,
+        lotteryPool: pool,
+        authority: wallet.publicKey,
+        validator: TEE_VALIDATOR,
+        bufferPlayerTicket: ticketPdas.buffer,
+        delegationRecord: ticketPdas.record,
+        delegationMetadata: ticketPdas.metadata,
+        ephemeralRollupsProgram: DELEGATION_PROGRAM_ID,
+        ownerProgram: l1Program.programId,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      } as any)
+      .rpc());
+  };
+
+  // Fresh buy_ticket session key scoped to `epoch`, delegated to the ER
+  const issueEpochSession = async (epoch: BN) => {
+    const key = Keypair.generate();
+    const [pda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("session"), wallet.publicKey.toBuffer(), key.publicKey.toBuffer()],
+      l1Program.programId
+    );
+    const scope = { instructions: SESSION_SCOPE_BUY_TICKET, epochId: epoch, pool: null, maxCredits: null };
+    await withRetry(() => l1Program.methods
+      .issueSession(key.publicKey, new BN(Math.floor(Date.now() / 1000) + 3600), scope)
+      .accounts({
+        authority: wallet.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      } as any)
+      .rpc());
+    const sessionPdas = delegationPdas(pda);
+    await withRetry(() => l1Program.methods
+      .delegateSession()
+      .accounts({
+        sessionToken: pda,
+        authority: wallet.publicKey,
+        validator: TEE_VALIDATOR,
+        bufferSessionToken: sessionPdas.buffer,
+        delegationRecord: sessionPdas.record,
+        delegationMetadata: sessionPdas.metadata,
+        delegationProgram: DELEGATION_PROGRAM_ID,
+        ownerProgram: l1Program.programId,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      } as any)
+      .rpc());
+    return { key, pda };
+  };
+
+  // Revoke the session on the ER, bring it back to L1 and close it
+  const closeEpochSession = async (session: { key: Keypair; pda: PublicKey }) => {
+    await withRetry(() => erProgram.methods
+      .revokeSession()
+      .accounts({ sessionToken: session.pda, authority: wallet.publicKey } as any)
+      .rpc());
+    await withRetry(() => erProgram.methods
+      .undelegateSession()
+      .accounts({
+        sessionToken: session.pda,
+        authority: wallet.publicKey,
+        magicContext: MAGIC_CONTEXT_ID,
+        magicProgram: MAGIC_PROGRAM_ID,
+      } as any)
+      .rpc());
+    await withRetry(async () => {
+      const info = await l1Connection.getAccountInfo(session.pda);
+      if (!info || !info.owner.equals(l1Program.programId)) {
+        throw new Error("SessionToken not yet undelegated on L1");
+      }
+    }, 10, 3000);
+    await withRetry(() => l1Program.methods
+      .closeSession()
+      .accounts({ sessionToken: session.pda, authority: wallet.publicKey } as any)
+      .rpc());
+  };
+
+  const buyEntry = (
+    epoch: BN,
+    session: { key: Keypair; pda: PublicKey },
+    ticketData: number[],
+    credits = 1,
+    weightTree: PublicKey | null = null
+  ) =>
+    erProgram.methods
+      .buyTicket(epoch, ticketData, new BN(credits))
+      .accounts({
+        lotteryPool: poolPdaFor(epoch),
+        playerTicket: playerTicketPdaFor(epoch),
+        authority: wallet.publicKey,
+        sessionToken: session.pda,
+        ephemeralSigner: session.key.publicKey,
+        feePayer: wallet.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        weightTree,
+      } as any)
+      .signers([session.key])
+      .rpc();
+
+  // Draw as the pool authority once `drawAt` passes and wait for the VRF callback on the ER
+  const drawOnEr = async (epoch: BN, drawAt: BN) => {
+    const pool = poolPdaFor(epoch);
+    await waitUntil(drawAt.toNumber());
+    await withRetry(() => erProgram.methods
+      .requestRandomness(epoch, 3)
+      .accounts({
+        lotteryPool: pool,
+        sessionToken: null,
+        ephemeralSigner: null,
+        payer: wallet.publicKey,
+        oracleQueue: EPHEMERAL_ORACLE_QUEUE,
+      } as any)
+      .rpc());
+    return withRetry(async () => {
+      const info = await erConnection.getAccountInfo(pool);
+      const state: any = erProgram.coder.accounts.decode("lotteryPool", info!.data);
+      if (state.winningTicketIds.length === 0 && state.winningNumbers.length === 0) {
+        throw new Error("VRF callback not yet processed");
+      }
+      return state;
+    }, 10, 3000);
+  };
+
+  // Commit and undelegate the pool (with a weighted epoch's tree) and the
+  // wallet's ticket, and wait until both are back on L1
+  const undelegateEpoch = async (epoch: BN, weighted = false) => {
+    const pool = poolPdaFor(epoch);
+    const ticket = playerTicketPdaFor(epoch);
+    await withRetry(() => erProgram.methods
+      .undelegatePool(epoch)
+      .accounts({
+        lotteryPool: pool,
+        payer: wallet.publicKey,
+        magicContext: MAGIC_CONTEXT_ID,
+        magicProgram: MAGIC_PROGRAM_ID,
+        weightTree: weighted ? weightTreePdaFor(epoch) : null,
+      } as any)
+      .rpc());
+    await withRetry(() => erProgram.methods
+      .undelegatePlayerTicket(epoch)
+      .accounts({
+        playerTicket: ticket,
+        owner: wallet.publicKey,
+        magicContext: MAGIC_CONTEXT_ID,
+        magicProgram: MAGIC_PROGRAM_ID,
+      } as any)
+      .rpc());
+    const accounts = weighted ? [pool, ticket, weightTreePdaFor(epoch)] : [pool, ticket];
+    await withRetry(async () => {
+      for (const account of accounts) {
+        const info = await l1Connection.getAccountInfo(account);
+        if (!info || !info.owner.equals(l1Program.programId)) {
+          throw new Error(`${account.toBase58()} not yet undelegated on L1`);
+        }
+      }
+    }, 10, 3000);
+  };
+
+  // Close the wallet's ticket and then the pool; both must be settled
+  const closeEpoch = async (epoch: BN, extra: Record<string, PublicKey | null> = {}) => {
+    await withRetry(() => l1Program.methods
+      .closePlayerTicket(epoch)
+      .accounts({
+        lotteryPool: poolPdaFor(epoch),
+        playerTicket: playerTicketPdaFor(epoch),
+        owner: wallet.publicKey,
+        closer: wallet.publicKey,
+      } as any)
+      .rpc());
+    await withRetry(() => l1Program.methods
+      .closeLotteryPool(epoch)
+      .accounts({
+        lotteryPool: poolPdaFor(epoch),
+        authority: wallet.publicKey,
+        rentPayer: wallet.publicKey,
+        poolVault: null,
+        tokenProgram: null,
+        weightTree: null,
+        ...extra,
+      } as any)
+      .rpc());
+    expect(await l1Connection.getAccountInfo(poolPdaFor(epoch))).to.equal(null);
+  };
+
+  /*
+   * Phase 0: Program config (created once by the upgrade authority)
+  */
+
+  it("Phase 0: Init or Update Program Config (Devnet)", async () => {
+    const configParams = {
+      protocolFeeBps,
+      feeRecipient: feeRecipient.publicKey,
+      minBasePrice: new BN(1),
+      maxBasePrice: new BN(1_000_000_000),
+      allowedValidators: [], // empty: only the TEE validator is allowed
+      lotteryCreators: [],
+      maxSessionLifetimeSecs,
+    };
+
+    const existing = await l1Connection.getAccountInfo(configPda);
+    if (existing === null) {
+      const [programData] = PublicKey.findProgramAddressSync(
+        [l1Program.programId.toBuffer()],
+        new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+      );
+      await withRetry(() => l1Program.methods
+        .initConfig(configParams)
+        .accounts({
+          admin: wallet.publicKey,
+          lotryProgram: l1Program.programId,
+          programData,
+        } as any)
+        .rpc());
+    } else {
+      await withRetry(() => l1Program.methods
+        .updateConfig(configParams)
+        .accounts({ admin: wallet.publicKey } as any)
+        .rpc());
+    }
+
+    const configState = await withRetry(() => l1Program.account.programConfig.fetch(configPda));
+    expect(configState.admin.toBase58()).to.equal(wallet.publicKey.toBase58());
+    expect(configState.protocolFeeBps).to.equal(protocolFeeBps);
+    expect(configState.feeRecipient.toBase58()).to.equal(feeRecipient.publicKey.toBase58());
+  });
+
   /*
    * Phase 1: Initialize the Lottery Pool on Devnet L1
   */
 
-  it("Phase 1: Initialize Lottery Pool (Devnet)", async () => {
+  it("Phase 1: Open Next Epoch of the Series (Devnet)", async () => {
+    if ((await l1Connection.getAccountInfo(seriesPda)) === null) {
+      await withRetry(() => l1Program.methods
+        .createSeries(seriesId, {
+          basePrice,
+          pricingCurve,
+          purchaseFeeBps,
+          payoutFeeBps,
+          drawAuthority: wallet.publicKey,
+          saleDurationSecs,
+          drawDelaySecs,
+          prizeTiers,
+          claimWindowSecs,
+          forfeitPolicy,
+          gameMode,
+          revealWindowSecs,
+          drawGraceSecs,
+        })
+        .accounts({
+          authority: l1Provider.wallet.publicKey,
+        } as any)
+        .rpc());
+    }
+
+    const seriesBefore = await withRetry(() => l1Program.account.lotterySeries.fetch(seriesPda));
+    epochId = seriesBefore.lastEpochId.addn(1);
+    poolPda = poolPdaFor(epochId);
+    [playerTicketPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("player_ticket"), wallet.publicKey.toBuffer(), epochId.toArrayLike(Buffer, "le", 8)],
+      l1Program.programId
+    );
 
     await withRetry(() => l1Program.methods
-      .initializeLottery(epochId, basePrice, curveMultiplier, taxRateBps)
+      .openNextEpoch(seriesId)
       .accounts({
-        authority: l1Provider.wallet.publicKey,
-      })
+        series: seriesPda,
+        lotteryPool: poolPda,
+        payer: l1Provider.wallet.publicKey,
+      } as any)
       .rpc());
 
-    console.log("Phase 1: Lottery Pool initialized for epoch", epochId.toNumber());
+    console.log("Phase 1: Series", seriesId, "opened epoch", epochId.toString());
+
+    const seriesAfter = await withRetry(() => l1Program.account.lotterySeries.fetch(seriesPda));
+    expect(seriesAfter.lastEpochId.toString()).to.equal(epochId.toString());
 
     const poolState = await withRetry(() => l1Program.account.lotteryPool.fetch(poolPda));
     expect(poolState.epochId.toNumber()).to.equal(epochId.toNumber());
     expect(poolState.basePrice.toNumber()).to.equal(basePrice.toNumber());
-    expect(poolState.curveMultiplier.toNumber()).to.equal(curveMultiplier.toNumber());
-    expect(poolState.taxRateBps).to.equal(taxRateBps);
+    expect(poolState.pricingCurve.linear.slope.toNumber()).to.equal(curveSlope.toNumber());
+    expect(poolState.creditsSold.toNumber()).to.equal(0);
+    expect(poolState.purchaseFeeBps).to.equal(purchaseFeeBps);
+    expect(poolState.payoutFeeBps).to.equal(payoutFeeBps);
+    expect(poolState.drawAuthority.toBase58()).to.equal(wallet.publicKey.toBase58());
+    expect(poolState.saleClosesAt.sub(poolState.saleOpensAt).toNumber()).to.equal(saleDurationSecs.toNumber());
+    expect(poolState.drawAt.sub(poolState.saleClosesAt).toNumber()).to.equal(drawDelaySecs.toNumber());
+    drawAt = poolState.drawAt;
+    expect(poolState.prizeTiers).to.deep.equal(prizeTiers);
+    expect(poolState.claimWindowSecs.toNumber()).to.equal(claimWindowSecs.toNumber());
+    expect(poolState.forfeitPolicy).to.deep.equal(forfeitPolicy);
+    expect(poolState.gameMode).to.deep.equal(gameMode);
+    expect(poolState.drawGraceSecs.toNumber()).to.equal(drawGraceSecs.toNumber());
+    expect(poolState.claimDeadline.toNumber()).to.equal(0);
+    expect(poolState.protocolFeeBps).to.equal(protocolFeeBps);
     expect(poolState.totalStakedSol.toNumber()).to.equal(0);
     expect(poolState.taxTreasurySol.toNumber()).to.equal(0);
   });
@@ -100,11 +585,15 @@ describe("lotry", () => {
       .initPlayerTicket(epochId)
       .accounts({
         playerTicket: playerTicketPda,
+        lotteryPool: poolPda,
         authority: l1Provider.wallet.publicKey,
         systemProgram: anchor.web3.SystemProgram.programId,
       } as any)
       .rpc());
 
+    const poolState = await withRetry(() => l1Program.account.lotteryPool.fetch(poolPda));
+    expect(poolState.openTickets.toNumber()).to.equal(1);
+
     const ticketState = await withRetry(() => l1Program.account.playerTicket.fetch(playerTicketPda));
     expect(ticketState.owner.toBase58()).to.equal(wallet.publicKey.toBase58());
     expect(ticketState.epochId.toNumber()).to.equal(epochId.toNumber());
@@ -115,10 +604,10 @@ describe("lotry", () => {
     const firstTickets = new BN(2);
     const secondTickets = new BN(1);
 
-    const purchase1 = calcPurchase(expectedTotalStaked, firstTickets);
+    const purchase1 = calcPurchase(expectedCreditsSold, firstTickets);
 
     await withRetry(() => l1Program.methods
-      .buyTicketCredits(epochId, firstTickets)
+      .buyTicketCredits(epochId, firstTickets, purchase1.totalPrice)
       .accounts({
         lotteryPool: poolPda,
         playerTicket: playerTicketPda,
@@ -130,6 +619,7 @@ describe("lotry", () => {
     expectedTotalStaked = expectedTotalStaked.add(purchase1.net);
     expectedTaxTreasury = expectedTaxTreasury.add(purchase1.tax);
     expectedTicketBalance = expectedTicketBalance.add(firstTickets);
+    expectedCreditsSold = expectedCreditsSold.add(firstTickets);
 
     let poolState = await withRetry(() => l1Program.account.lotteryPool.fetch(poolPda));
     let ticketState = await withRetry(() => l1Program.account.playerTicket.fetch(playerTicketPda));
@@ -137,11 +627,33 @@ describe("lotry", () => {
     expect(poolState.taxTreasurySol.toNumber()).to.equal(expectedTaxTreasury.toNumber());
     expect(ticketState.balance.toNumber()).to.equal(expectedTicketBalance.toNumber());
 
-    const purchase2 = calcPurchase(expectedTotalStaked, secondTickets);
-    expect(purchase2.totalPrice.gt(purchase1.totalPrice)).to.equal(true);
+    const purchase2 = calcPurchase(expectedCreditsSold, secondTickets);
+    expect(purchase2.currentPrice.gt(purchase1.currentPrice)).to.equal(true);
 
-    await withRetry(() => l1Program.methods
-      .buyTicketCredits(epochId, secondTickets)
+    const quoted = await withRetry(() => l1Program.methods
+      .quoteTicketCredits(epochId, secondTickets)
+      .accounts({ lotteryPool: poolPda } as any)
+      .view());
+    expect(quoted.toNumber()).to.equal(purchase2.totalPrice.toNumber());
+
+    // A limit below the quote must fail instead of overpaying
+    try {
+      await l1Program.methods
+        .buyTicketCredits(epochId, secondTickets, quoted.subn(1))
+        .accounts({
+          lotteryPool: poolPda,
+          playerTicket: playerTicketPda,
+          buyer: l1Provider.wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        } as any)
+        .rpc();
+      expect.fail("purchase above max_total_price should fail");
+    } catch (e: any) {
+      expect(e.error?.errorCode?.code).to.equal("PriceExceedsLimit");
+    }
+
+    const purchase2Sig = await withRetry(() => l1Program.methods
+      .buyTicketCredits(epochId, secondTickets, quoted)
       .accounts({
         lotteryPool: poolPda,
         playerTicket: playerTicketPda,
@@ -150,15 +662,23 @@ describe("lotry", () => {
       } as any)
       .rpc());
 
+    const [purchased] = await withRetry(() => cpiEvents(purchase2Sig));
+    expect(purchased.data.ticketAmount.toNumber()).to.equal(secondTickets.toNumber());
+    expect(purchased.data.tax.toNumber()).to.equal(purchase2.tax.toNumber());
+
     expectedTotalStaked = expectedTotalStaked.add(purchase2.net);
     expectedTaxTreasury = expectedTaxTreasury.add(purchase2.tax);
     expectedTicketBalance = expectedTicketBalance.add(secondTickets);
+    expectedCreditsSold = expectedCreditsSold.add(secondTickets);
 
     poolState = await withRetry(() => l1Program.account.lotteryPool.fetch(poolPda));
     ticketState = await withRetry(() => l1Program.account.playerTicket.fetch(playerTicketPda));
     expect(poolState.totalStakedSol.toNumber()).to.equal(expectedTotalStaked.toNumber());
     expect(poolState.taxTreasurySol.toNumber()).to.equal(expectedTaxTreasury.toNumber());
     expect(ticketState.balance.toNumber()).to.equal(expectedTicketBalance.toNumber());
+    // Everything paid is tracked so it can be refunded if the epoch is cancelled
+    expect(ticketState.paidSol.toNumber()).to.equal(purchase1.totalPrice.add(purchase2.totalPrice).toNumber());
+    expect(ticketState.paidTaxSol.toNumber()).to.equal(purchase1.tax.add(purchase2.tax).toNumber());
   });
 
   it("Phase 4: Delegate Lottery Pool to ER (Devnet)", async () => {
@@ -207,6 +727,9 @@ describe("lotry", () => {
     // Verification: Re-fetch the account info and check the owner has changed to DELEGATION_PROGRAM_ID
     const poolAccountInfo = await withRetry(() => l1Connection.getAccountInfo(poolPda));
     expect(poolAccountInfo?.owner.toBase58()).to.equal(DELEGATION_PROGRAM_ID.toBase58());
+    // The pool pins the validator so player tickets follow it
+    const delegatedPool: any = l1Program.coder.accounts.decode("lotteryPool", poolAccountInfo!.data);
+    expect(delegatedPool.validator.toBase58()).to.equal(TEE_VALIDATOR.toBase58());
   });
 
   it("Phase 5: Delegate PlayerTicket to ER (Devnet)", async () => {
@@ -230,6 +753,7 @@ describe("lotry", () => {
       .delegatePlayerTicket(epochId)
       .accounts({
         playerTicket: playerTicketPda,
+        lotteryPool: poolPda,
         authority: l1Provider.wallet.publicKey,
         validator: TEE_VALIDATOR,
         bufferPlayerTicket: ticketBufferPda,
@@ -253,10 +777,18 @@ describe("lotry", () => {
     expect(ticketAccountInfo?.owner.toBase58()).to.equal(DELEGATION_PROGRAM_ID.toBase58());
   });
 
-  it("Phase 6: Issue Session Key (Devnet)", async () => {
+  it("Phase 6: Issue and Delegate Session Key (Devnet)", async () => {
+    // Scoped to this epoch: buy_ticket + request_randomness, at most two credits
+    const scope = {
+      instructions: SESSION_SCOPE_BUY_TICKET | SESSION_SCOPE_REQUEST_RANDOMNESS,
+      epochId,
+      pool: null,
+      maxCredits: new BN(sessionCreditLimit),
+    };
+
     // Issue on L1 devnet to mirror real session flow
     await withRetry(() => l1Program.methods
-      .issueSession(sessionKey.publicKey, new BN(validUntil))
+      .issueSession(sessionKey.publicKey, new BN(validUntil), scope)
       .accounts({
         authority: l1Provider.wallet.publicKey,
         systemProgram: anchor.web3.SystemProgram.programId,
@@ -268,53 +800,162 @@ describe("lotry", () => {
     expect(sessionState.authority.toBase58()).to.equal(l1Provider.wallet.publicKey.toBase58());
     expect(sessionState.ephemeralKey.toBase58()).to.equal(sessionKey.publicKey.toBase58());
     expect(sessionState.validUntil.toNumber()).to.be.closeTo(validUntil, 5);
-  });
+    expect(sessionState.scope.epochId.toString()).to.equal(epochId.toString());
+    expect(sessionState.creditsRemaining.toNumber()).to.equal(sessionCreditLimit);
 
-  it("Phase 7: Buy Ticket via Session Key on ER (Devnet)", async () => {
-    const ticketData = Array.from(randomBytes(32));
+    // Extend the same key instead of issuing a fresh one
+    const renewedUntil = validUntil + 600;
+    await withRetry(() => l1Program.methods
+      .renewSession(new BN(renewedUntil))
+      .accounts({
+        sessionToken: sessionPda,
+        authority: l1Provider.wallet.publicKey,
+      } as any)
+      .rpc());
+    const renewedState = await withRetry(() => l1Program.account.sessionToken.fetch(sessionPda));
+    expect(renewedState.validUntil.toNumber()).to.equal(renewedUntil);
 
     try {
-      const tx = await withRetry(() => erProgram.methods
-        .buyTicket(epochId, Array.from(ticketData))
+      await l1Program.methods
+        .renewSession(new BN(Math.floor(Date.now() / 1000)).add(maxSessionLifetimeSecs).addn(600))
         .accounts({
-          lotteryPool: poolPda,
-          playerTicket: playerTicketPda,
-          authority: wallet.publicKey,
           sessionToken: sessionPda,
-          ephemeralSigner: sessionKey.publicKey,
-          feePayer: wallet.publicKey,
-          systemProgram: anchor.web3.SystemProgram.programId,
+          authority: l1Provider.wallet.publicKey,
         } as any)
-        .signers([sessionKey])
-        .rpc());
-      console.log("BuyTicket successful on ER! TX:", tx);
+        .rpc();
+      expect.fail("renewal past the configured lifetime should fail");
     } catch (e: any) {
-      console.error("Phase 7 Failed! Logs:", e.logs);
-      throw e;
+      expect(e.error?.errorCode?.code).to.equal("SessionLifetimeExceeded");
+    }
+
+    // buy_ticket draws down the allowance on the ER, so the token must live there
+    const DELEGATION_PROGRAM_ID = new PublicKey("DELeGGvXpWV2fqJUhqcF5ZSYMS4JTLjteaAMARRSaeSh");
+    const TEE_VALIDATOR = new PublicKey("MAS1Dt9qreoRMQ14YQuhg8UTZMMzDdKhmkZMECCzk57");
+    const [sessionBufferPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("buffer"), sessionPda.toBuffer()],
+      l1Program.programId
+    );
+    const [sessionDelegationRecordPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("delegation"), sessionPda.toBuffer()],
+      DELEGATION_PROGRAM_ID
+    );
+    const [sessionDelegationMetadataPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("delegation-metadata"), sessionPda.toBuffer()],
+      DELEGATION_PROGRAM_ID
+    );
+
+    await withRetry(() => l1Program.methods
+      .delegateSession()
+      .accounts({
+        sessionToken: sessionPda,
+        authority: l1Provider.wallet.publicKey,
+        validator: TEE_VALIDATOR,
+        bufferSessionToken: sessionBufferPda,
+        delegationRecord: sessionDelegationRecordPda,
+        delegationMetadata: sessionDelegationMetadataPda,
+        delegationProgram: DELEGATION_PROGRAM_ID,
+        ownerProgram: l1Program.programId,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      } as any)
+      .rpc());
+
+    const delegatedInfo = await withRetry(() => l1Connection.getAccountInfo(sessionPda));
+    expect(delegatedInfo?.owner.toBase58()).to.equal(DELEGATION_PROGRAM_ID.toBase58());
+  });
+
+  it("Phase 7: Buy Tickets via Session Key on ER (Devnet)", async () => {
+    // Spend two credits as one entry: random-ticket entries hold a run of ticket ids
+    const entryCredits = [2];
+    const creditsSpent = entryCredits.reduce((a, b) => a + b, 0);
+
+    for (const [i, credits] of entryCredits.entries()) {
+      const ticketData = Array.from(randomBytes(32));
+      try {
+        const tx = await withRetry(() => erProgram.methods
+          .buyTicket(epochId, ticketData, new BN(credits))
+          .accounts({
+            lotteryPool: poolPda,
+            playerTicket: playerTicketPda,
+            authority: wallet.publicKey,
+            sessionToken: sessionPda,
+            ephemeralSigner: sessionKey.publicKey,
+            feePayer: wallet.publicKey,
+            systemProgram: anchor.web3.SystemProgram.programId,
+          } as any)
+          .signers([sessionKey])
+          .rpc());
+        console.log(`BuyTicket #${i} successful on ER! TX:`, tx);
+      } catch (e: any) {
+        console.error("Phase 7 Failed! Logs:", e.logs);
+        throw e;
+      }
+      expectedTicketBalance = expectedTicketBalance.subn(credits);
     }
 
     const ticketAccountInfo = await withRetry(() => erConnection.getAccountInfo(playerTicketPda));
     const ticketState: any = erProgram.coder.accounts.decode("playerTicket", ticketAccountInfo!.data);
     expect(ticketState.owner.toBase58()).to.equal(wallet.publicKey.toBase58());
     expect(ticketState.epochId.toNumber()).to.equal(epochId.toNumber());
-    expectedTicketBalance = expectedTicketBalance.subn(1);
-    expect(ticketState.ticketId.toNumber()).to.equal(0);
+    expect(ticketState.entries.map((e: any) => e.ticketId.toNumber())).to.deep.equal([0]);
+    expect(ticketState.entries.map((e: any) => e.count.toNumber())).to.deep.equal(entryCredits);
     expect(ticketState.balance.toNumber()).to.equal(expectedTicketBalance.toNumber());
     expect(ticketState.isActive).to.equal(true);
 
     const updatedPoolInfo = await withRetry(() => erConnection.getAccountInfo(poolPda));
     const updatedPool: any = erProgram.coder.accounts.decode("lotteryPool", updatedPoolInfo!.data);
-    expect(updatedPool.ticketCount.toNumber()).to.equal(1);
+    expect(updatedPool.ticketCount.toNumber()).to.equal(creditsSpent);
+
+    const sessionInfo = await withRetry(() => erConnection.getAccountInfo(sessionPda));
+    const sessionState: any = erProgram.coder.accounts.decode("sessionToken", sessionInfo!.data);
+    expect(sessionState.creditsRemaining.toNumber()).to.equal(sessionCreditLimit - creditsSpent);
+
+    // The session's allowance is spent even though the ticket still holds credits
+    try {
+      await erProgram.methods
+        .buyTicket(epochId, Array.from(randomBytes(32)), new BN(1))
+        .accounts({
+          lotteryPool: poolPda,
+          playerTicket: playerTicketPda,
+          authority: wallet.publicKey,
+          sessionToken: sessionPda,
+          ephemeralSigner: sessionKey.publicKey,
+          feePayer: wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        } as any)
+        .signers([sessionKey])
+        .rpc();
+      expect.fail("buy_ticket beyond the session allowance should fail");
+    } catch (e: any) {
+      expect(e.error?.errorCode?.code).to.equal("SessionCreditLimitReached");
+    }
   });
 
-  it("Phase 8: Request Winner via Session Key (Devnet)", async () => {
-    const [poolPda] = PublicKey.findProgramAddressSync(
-      [Buffer.from("lottery_pool"), epochId.toArrayLike(Buffer, "le", 8)],
-      l1Program.programId
-    );
+  it("Phase 7b: Checkpoint Pool to L1 While Delegated (Devnet)", async () => {
+    await withRetry(() => erProgram.methods
+      .checkpointPool(epochId)
+      .accounts({
+        lotteryPool: poolPda,
+        payer: wallet.publicKey,
+        magicContext: MAGIC_CONTEXT_ID,
+        magicProgram: MAGIC_PROGRAM_ID,
+      } as any)
+      .remainingAccounts([{ pubkey: playerTicketPda, isWritable: true, isSigner: false }])
+      .rpc());
 
-    // Simplified Phase 5: Request Winner directly (Step 3 Isolation)
+    // The pool stays delegated; its L1 copy catches up once the commit lands
+    const l1Pool: any = await withRetry(async () => {
+      const info = await l1Connection.getAccountInfo(poolPda);
+      const pool: any = l1Program.coder.accounts.decode("lotteryPool", info!.data);
+      if (pool.ticketCount.toNumber() === 0) {
+        throw new Error("Checkpoint not yet committed on L1");
+      }
+      return { owner: info!.owner, pool };
+    }, 10, 3000);
+    expect(l1Pool.owner.toBase58()).to.not.equal(l1Program.programId.toBase58());
+    expect(l1Pool.pool.ticketCount.toNumber()).to.equal(2);
+  });
 
+  it("Phase 8: Request VRF Randomness via Authority Session Key (Devnet)", async () => {
     const poolAccountInfo = await withRetry(() => erConnection.getAccountInfo(poolPda));
     if (!poolAccountInfo) {
       throw new Error("LotteryPool account not found on connection");
@@ -323,30 +964,47 @@ describe("lotry", () => {
     const ticketCount = poolState.ticketCount.toNumber();
     expect(ticketCount).to.be.greaterThan(0);
 
-
     const clientSeed = 7;
 
+    // The program refuses to draw before drawAt
+    const waitMs = (drawAt.toNumber() + 2) * 1000 - Date.now();
+    if (waitMs > 0) {
+      console.log(`Waiting ${Math.ceil(waitMs / 1000)}s for the scheduled draw time...`);
+      await new Promise(r => setTimeout(r, waitMs));
+    }
+
     const requestTx = await withRetry(() => erProgram.methods
-      .requestWinner(epochId, clientSeed)
+      .requestRandomness(epochId, clientSeed)
       .accounts({
         lotteryPool: poolPda,
-        authority: wallet.publicKey,
         sessionToken: sessionPda,
         ephemeralSigner: sessionKey.publicKey,
+        payer: wallet.publicKey,
+        oracleQueue: EPHEMERAL_ORACLE_QUEUE,
       } as any)
       .signers([sessionKey])
       .rpc());
 
-    console.log("Winner selected on ER! TX:", requestTx);
+    console.log("Randomness requested on ER! TX:", requestTx);
 
-    const finalPoolInfo = await withRetry(() => erConnection.getAccountInfo(poolPda));
-    const finalPool: any = erProgram.coder.accounts.decode("lotteryPool", finalPoolInfo.data);
+    // The VRF oracle settles the draw through the consume_randomness callback
+    const finalPool: any = await withRetry(async () => {
+      const info = await erConnection.getAccountInfo(poolPda);
+      const pool: any = erProgram.coder.accounts.decode("lotteryPool", info!.data);
+      if (pool.winningTicketIds.length === 0) {
+        throw new Error("VRF callback not yet processed");
+      }
+      return pool;
+    }, 10, 3000);
 
-    console.log("\nWinner selected! Ticket ID:", finalPool.winnerTicketId.toNumber());
+    const winningIds: number[] = finalPool.winningTicketIds.map((id: BN) => id.toNumber());
+    console.log("\nWinners selected! Ticket IDs:", winningIds);
     expect(finalPool.isActive).to.equal(false);
-    expect(finalPool.winnerTicketId).to.not.equal(null);
-    expect(finalPool.winnerTicketId.toNumber()).to.be.at.least(0);
-    expect(finalPool.winnerTicketId.toNumber()).to.be.lessThan(ticketCount);
+    expect(finalPool.vrfRequestId).to.not.equal(null);
+    expect(winningIds.length).to.equal(Math.min(prizeTiers.length, ticketCount));
+    expect(new Set(winningIds).size).to.equal(winningIds.length);
+    winningIds.forEach((id) => expect(id).to.be.lessThan(ticketCount));
+    expect(finalPool.prizePoolSol.toNumber()).to.equal(expectedTotalStaked.toNumber());
   });
 
   it("Phase 9: Commit & Undelegate Lottery Pool (Devnet)", async () => {
@@ -358,13 +1016,23 @@ describe("lotry", () => {
         magicContext: MAGIC_CONTEXT_ID,
         magicProgram: MAGIC_PROGRAM_ID,
       } as any)
-      .remainingAccounts([
-        { pubkey: playerTicketPda, isWritable: true, isSigner: false },
-      ])
       .rpc());
 
     console.log("Commit + undelegate scheduled on ER! TX:", commitTx);
 
+    // The winner brings their own ticket back to L1 to claim
+    const ticketTx = await withRetry(() => erProgram.methods
+      .undelegatePlayerTicket(epochId)
+      .accounts({
+        playerTicket: playerTicketPda,
+        owner: wallet.publicKey,
+        magicContext: MAGIC_CONTEXT_ID,
+        magicProgram: MAGIC_PROGRAM_ID,
+      } as any)
+      .rpc());
+
+    console.log("PlayerTicket undelegate scheduled on ER! TX:", ticketTx);
+
     const l1States = await withRetry(async () => {
       const l1PoolInfo = await l1Connection.getAccountInfo(poolPda);
       if (!l1PoolInfo) {
@@ -377,8 +1045,8 @@ describe("lotry", () => {
       if (poolState.isActive) {
         throw new Error("LotteryPool still active on L1");
       }
-      if (poolState.winnerTicketId === null) {
-        throw new Error("Winner not committed on L1");
+      if (poolState.winningTicketIds.length === 0) {
+        throw new Error("Winners not committed on L1");
       }
 
       const l1TicketInfo = await l1Connection.getAccountInfo(playerTicketPda);
@@ -394,55 +1062,212 @@ describe("lotry", () => {
 
     const { poolState: l1PoolState, ticketState: l1TicketState } = l1States;
 
-    console.log("LotteryPool committed on L1. Winner ticket:", l1PoolState.winnerTicketId.toNumber());
+    console.log("LotteryPool committed on L1. Winning tickets:", l1PoolState.winningTicketIds.map((id: BN) => id.toNumber()));
     expect(l1PoolState.isActive).to.equal(false);
-    expect(l1PoolState.winnerTicketId.toNumber()).to.be.at.least(0);
+    expect(l1PoolState.winningTicketIds.length).to.be.greaterThan(0);
+    expect(l1PoolState.claimDeadline.toNumber()).to.be.greaterThan(Math.floor(Date.now() / 1000));
     expect(l1TicketState.balance.toNumber()).to.equal(expectedTicketBalance.toNumber());
   });
 
-  it("Phase 10: Claim Prize on L1 (Devnet)", async () => {
-    const withdrawalTax = expectedTotalStaked.muln(taxRateBps).divn(10_000);
-    const payout = expectedTotalStaked.sub(withdrawalTax);
+  it("Phase 10: Claim Prize Tiers on L1 (Devnet)", async () => {
+    // The wallet holds every ticket, so it claims each drawn tier in turn
+    const prizePool = expectedTotalStaked;
+    const poolBefore = await withRetry(() => l1Program.account.lotteryPool.fetch(poolPda));
+    const drawnTiers = poolBefore.winningTicketIds.length;
 
-    await withRetry(() => l1Program.methods
-      .claimPrize(epochId)
-      .accounts({
-        lotteryPool: poolPda,
-        playerTicket: playerTicketPda,
-        winner: wallet.publicKey,
-        systemProgram: anchor.web3.SystemProgram.programId,
+    // Random-ticket entries carry no commitment to reveal
+    try {
+      await l1Program.methods
+        .revealTicket(epochId, poolBefore.winningTicketIds[0], 1, Array.from(randomBytes(32)))
+        .accounts({
+          lotteryPool: poolPda,
+          playerTicket: playerTicketPda,
+          owner: wallet.publicKey,
+        } as any)
+        .rpc();
+      expect.fail("reveal in a random-ticket pool should fail");
+    } catch (e: any) {
+      expect(e.error?.errorCode?.code).to.equal("InvalidGameMode");
+    }
+    // ...nor Pick-N numbers to register
+    try {
+      await l1Program.methods
+        .registerTicket(epochId)
+        .accounts({
+          lotteryPool: poolPda,
+          playerTicket: playerTicketPda,
+          owner: wallet.publicKey,
+        } as any)
+        .rpc();
+      expect.fail("register in a random-ticket pool should fail");
+    } catch (e: any) {
+      expect(e.error?.errorCode?.code).to.equal("InvalidGameMode");
+    }
+
+    for (let tier = 0; tier < drawnTiers; tier++) {
+      const share = prizePool.muln(prizeTiers[tier]).divn(10_000);
+      const withdrawalTax = share.muln(payoutFeeBps).divn(10_000);
+
+      await withRetry(() => l1Program.methods
+        .claimPrize(epochId, tier)
+        .accounts({
+          lotteryPool: poolPda,
+          playerTicket: playerTicketPda,
+          winner: wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        } as any)
+        .rpc());
+
+      expectedTaxTreasury = expectedTaxTreasury.add(withdrawalTax);
+      expectedTotalStaked = expectedTotalStaked.sub(share);
+      console.log(`Tier ${tier} claimed on L1. Payout:`, share.sub(withdrawalTax).toNumber());
+    }
+
+    const poolState = await withRetry(() => l1Program.account.lotteryPool.fetch(poolPda));
+    expect(poolState.totalStakedSol.toNumber()).to.equal(expectedTotalStaked.toNumber());
+    expect(poolState.taxTreasurySol.toNumber()).to.equal(expectedTaxTreasury.toNumber());
+    expect(poolState.claimedTiers).to.equal((1 << drawnTiers) - 1);
+
+    // A tier can only be paid once
+    try {
+      await l1Program.methods
+        .claimPrize(epochId, 0)
+        .accounts({
+          lotteryPool: poolPda,
+          playerTicket: playerTicketPda,
+          winner: wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        } as any)
+        .rpc();
+      expect.fail("second claim of the same tier should fail");
+    } catch (e: any) {
+      expect(e.error?.errorCode?.code).to.equal("PrizeAlreadyClaimed");
+    }
+  });
+
+  it("Phase 11: Roll Undrawn Tier Over Into Next Epoch (Devnet)", async () => {
+    // Tier 2 had no ticket left to win it, so its share stays in the pool
+    const nextEpochId = epochId.addn(1);
+    const nextPoolPda = poolPdaFor(nextEpochId);
+
+    await withRetry(() => l1Program.methods
+      .openNextEpoch(seriesId)
+      .accounts({
+        series: seriesPda,
+        lotteryPool: nextPoolPda,
+        payer: l1Provider.wallet.publicKey,
       } as any)
       .rpc());
 
-    expectedTaxTreasury = expectedTaxTreasury.add(withdrawalTax);
+    const leftover = expectedTotalStaked;
+    await withRetry(() => l1Program.methods
+      .rolloverJackpot(epochId, nextEpochId)
+      .accounts({
+        lotteryPool: poolPda,
+        nextPool: nextPoolPda,
+        authority: wallet.publicKey,
+      } as any)
+      .rpc());
     expectedTotalStaked = new BN(0);
 
     const poolState = await withRetry(() => l1Program.account.lotteryPool.fetch(poolPda));
-    const ticketState = await withRetry(() => l1Program.account.playerTicket.fetch(playerTicketPda));
     expect(poolState.totalStakedSol.toNumber()).to.equal(0);
-    expect(poolState.taxTreasurySol.toNumber()).to.equal(expectedTaxTreasury.toNumber());
-    expect(ticketState.isActive).to.equal(false);
+    expect(poolState.rolledOverTo.toString()).to.equal(nextEpochId.toString());
 
-    console.log("Prize claimed on L1. Payout:", payout.toNumber());
+    const nextPoolState = await withRetry(() => l1Program.account.lotteryPool.fetch(nextPoolPda));
+    expect(nextPoolState.totalStakedSol.toNumber()).to.equal(leftover.toNumber());
+    expect(nextPoolState.rolledInSol.toNumber()).to.equal(leftover.toNumber());
+    console.log("Rolled", leftover.toNumber(), "into epoch", nextEpochId.toNumber());
   });
 
-  it("Phase 11: Withdraw Taxes to Treasury (Devnet)", async () => {
-    const existingBalance = await l1Connection.getBalance(treasury.publicKey);
-    if (existingBalance === 0) {
-      const fundIx = anchor.web3.SystemProgram.transfer({
-        fromPubkey: wallet.publicKey,
-        toPubkey: treasury.publicKey,
-        lamports: 1_000_000,
-      });
-      const fundTx = new anchor.web3.Transaction().add(fundIx);
-      fundTx.recentBlockhash = (await l1Connection.getLatestBlockhash()).blockhash;
-      fundTx.feePayer = wallet.publicKey;
-      await anchor.web3.sendAndConfirmTransaction(l1Connection, fundTx, [(wallet as any).payer ?? wallet], {
-        commitment: "confirmed",
-      });
-    }
+  it("Phase 11b: Cancel Next Epoch and Roll Its Carried-In Stake On (Devnet)", async () => {
+    // The epoch that just received the leftover sells credits and is then cancelled
+    const nextEpochId = epochId.addn(1);
+    const nextPoolPda = poolPdaFor(nextEpochId);
+    const nextTicketPda = playerTicketPdaFor(nextEpochId);
+    const carried = (await withRetry(() => l1Program.account.lotteryPool.fetch(nextPoolPda))).rolledInSol;
+
+    await withRetry(() => l1Program.methods
+      .initPlayerTicket(nextEpochId)
+      .accounts({
+        playerTicket: nextTicketPda,
+        lotteryPool: nextPoolPda,
+        authority: wallet.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      } as any)
+      .rpc());
+    const purchase = calcPurchase(new BN(0), new BN(2));
+    await withRetry(() => l1Program.methods
+      .buyTicketCredits(nextEpochId, new BN(2), purchase.totalPrice)
+      .accounts({
+        lotteryPool: nextPoolPda,
+        playerTicket: nextTicketPda,
+        buyer: wallet.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      } as any)
+      .rpc());
+    await withRetry(() => l1Program.methods
+      .cancelEpoch(nextEpochId)
+      .accounts({ lotteryPool: nextPoolPda, authority: wallet.publicKey } as any)
+      .rpc());
+
+    // Only the carried-in stake moves on; what the player paid stays for the refund
+    const laterEpochId = nextEpochId.addn(1);
+    await withRetry(() => l1Program.methods
+      .openNextEpoch(seriesId)
+      .accounts({
+        series: seriesPda,
+        lotteryPool: poolPdaFor(laterEpochId),
+        payer: wallet.publicKey,
+      } as any)
+      .rpc());
+    await withRetry(() => l1Program.methods
+      .rolloverJackpot(nextEpochId, laterEpochId)
+      .accounts({
+        lotteryPool: nextPoolPda,
+        nextPool: poolPdaFor(laterEpochId),
+        authority: wallet.publicKey,
+      } as any)
+      .rpc());
+    const laterPoolState = await withRetry(() => l1Program.account.lotteryPool.fetch(poolPdaFor(laterEpochId)));
+    expect(laterPoolState.rolledInSol.toNumber()).to.equal(carried.toNumber());
+
+    // The purchase tax is still owed to the player, not to the treasury
+    await expectError(() => l1Program.methods
+      .withdrawTaxes(nextEpochId)
+      .accounts({
+        lotteryPool: nextPoolPda,
+        authority: wallet.publicKey,
+        treasury: treasury.publicKey,
+        feeRecipient: feeRecipient.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      } as any)
+      .rpc(), "EpochCancelled");
+
+    await withRetry(() => l1Program.methods
+      .claimRefund(nextEpochId)
+      .accounts({
+        lotteryPool: nextPoolPda,
+        playerTicket: nextTicketPda,
+        player: wallet.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      } as any)
+      .rpc());
+    const poolState = await withRetry(() => l1Program.account.lotteryPool.fetch(nextPoolPda));
+    expect(poolState.refundedSol.toNumber()).to.equal(purchase.totalPrice.toNumber());
+    expect(poolState.totalStakedSol.toNumber()).to.equal(0);
+    expect(poolState.taxTreasurySol.toNumber()).to.equal(0);
+    await closeEpoch(nextEpochId);
+  });
+
+  it("Phase 12: Withdraw Taxes to Treasury (Devnet)", async () => {
+    // Both recipients need to be rent-exempt before receiving small fee amounts
+    await fundIfEmpty(treasury.publicKey);
+    await fundIfEmpty(feeRecipient.publicKey);
 
     const balanceBefore = await l1Connection.getBalance(treasury.publicKey);
+    const feeBalanceBefore = await l1Connection.getBalance(feeRecipient.publicKey);
+    const protocolFee = expectedTaxTreasury.muln(protocolFeeBps).divn(10_000);
 
     await withRetry(() => l1Program.methods
       .withdrawTaxes(epochId)
@@ -450,14 +1275,852 @@ describe("lotry", () => {
         lotteryPool: poolPda,
         authority: wallet.publicKey,
         treasury: treasury.publicKey,
+        feeRecipient: feeRecipient.publicKey,
         systemProgram: anchor.web3.SystemProgram.programId,
       } as any)
       .rpc());
 
     const balanceAfter = await l1Connection.getBalance(treasury.publicKey);
-    expect(balanceAfter - balanceBefore).to.equal(expectedTaxTreasury.toNumber());
+    expect(balanceAfter - balanceBefore).to.equal(expectedTaxTreasury.sub(protocolFee).toNumber());
+    const feeBalanceAfter = await l1Connection.getBalance(feeRecipient.publicKey);
+    expect(feeBalanceAfter - feeBalanceBefore).to.equal(protocolFee.toNumber());
 
+    // Every tier is settled and the leftover already rolled over, so only taxes go out
     const poolState = await withRetry(() => l1Program.account.lotteryPool.fetch(poolPda));
     expect(poolState.taxTreasurySol.toNumber()).to.equal(0);
+    expect(poolState.totalStakedSol.toNumber()).to.equal(0);
+  });
+
+  it("Phase 13: Revoke, Undelegate and Close Session (Devnet)", async () => {
+    // The token is delegated, so it is revoked on the ER
+    await withRetry(() => erProgram.methods
+      .revokeSession()
+      .accounts({
+        sessionToken: sessionPda,
+        authority: wallet.publicKey,
+      } as any)
+      .rpc());
+
+    await withRetry(() => erProgram.methods
+      .undelegateSession()
+      .accounts({
+        sessionToken: sessionPda,
+        authority: wallet.publicKey,
+        magicContext: MAGIC_CONTEXT_ID,
+        magicProgram: MAGIC_PROGRAM_ID,
+      } as any)
+      .rpc());
+
+    const sessionState = await withRetry(async () => {
+      const info = await l1Connection.getAccountInfo(sessionPda);
+      if (!info || !info.owner.equals(l1Program.programId)) {
+        throw new Error("SessionToken not yet undelegated on L1");
+      }
+      return l1Program.account.sessionToken.fetch(sessionPda);
+    }, 10, 3000);
+    expect(sessionState.isRevoked).to.equal(true);
+
+    await withRetry(() => l1Program.methods
+      .closeSession()
+      .accounts({
+        sessionToken: sessionPda,
+        authority: wallet.publicKey,
+      } as any)
+      .rpc());
+
+    expect(await l1Connection.getAccountInfo(sessionPda)).to.equal(null);
+    console.log("Session revoked and closed");
+  });
+
+  it("Phase 14: Close PlayerTicket and LotteryPool to Reclaim Rent (Devnet)", async () => {
+    // Every tier the ticket won is claimed, so it can be closed right away
+    await withRetry(() => l1Program.methods
+      .closePlayerTicket(epochId)
+      .accounts({
+        lotteryPool: poolPda,
+        playerTicket: playerTicketPda,
+        owner: wallet.publicKey,
+        closer: wallet.publicKey,
+      } as any)
+      .rpc());
+    expect(await l1Connection.getAccountInfo(playerTicketPda)).to.equal(null);
+
+    // Prizes claimed, the rest rolled over and taxes withdrawn: nothing left to hold
+    await withRetry(() => l1Program.methods
+      .closeLotteryPool(epochId)
+      .accounts({
+        lotteryPool: poolPda,
+        authority: wallet.publicKey,
+        rentPayer: wallet.publicKey,
+        poolVault: null,
+        tokenProgram: null,
+      } as any)
+      .rpc());
+    expect(await l1Connection.getAccountInfo(poolPda)).to.equal(null);
+    console.log("PlayerTicket and LotteryPool of epoch", epochId.toNumber(), "closed");
+  });
+
+  /*
+   * Weighted epoch: odds proportional to the credits each entry commits
+  */
+
+  describe("Weighted epoch", () => {
+    // Two tiers, two entries: both entries win, in an order the weights decide
+    const weights = [4, 1];
+    const totalWeight = weights.reduce((a, b) => a + b, 0);
+    let epoch: BN;
+    let pool: PublicKey;
+    let ticket: PublicKey;
+    let tree: PublicKey;
+    let drawAt: BN;
+    let session: { key: Keypair; pda: PublicKey };
+    let prizePool: BN;
+
+    it("Creates the WeightTree and sells credits (Devnet)", async () => {
+      const created = await initStandaloneEpoch({ gameMode: { weighted: {} }, prizeTiers: [7000, 3000] });
+      ({ epoch, pool, ticket } = created);
+      drawAt = created.params.drawAt;
+      tree = weightTreePdaFor(epoch);
+
+      await withRetry(() => l1Program.methods
+        .initWeightTree(epoch)
+        .accounts({
+          lotteryPool: pool,
+          weightTree: tree,
+          authority: wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        } as any)
+        .rpc());
+      const treeState = await withRetry(() => l1Program.account.weightTree.fetch(tree));
+      expect(treeState.epochId.toString()).to.equal(epoch.toString());
+      expect(treeState.totalWeight.toNumber()).to.equal(0);
+
+      // Weighted entries may spend any number of credits, so there is no per-player cap
+      const credits = new BN(totalWeight);
+      await withRetry(() => l1Program.methods
+        .buyTicketCredits(epoch, credits, basePrice.mul(credits))
+        .accounts({
+          lotteryPool: pool,
+          playerTicket: ticket,
+          buyer: wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        } as any)
+        .rpc());
+      const total = basePrice.mul(credits);
+      prizePool = total.sub(total.muln(purchaseFeeBps).divn(10_000));
+
+      const ticketState = await withRetry(() => l1Program.account.playerTicket.fetch(ticket));
+      expect(ticketState.balance.toNumber()).to.equal(totalWeight);
+    });
+
+    it("Enters weighted tickets on the ER (Devnet)", async () => {
+      await delegateEpoch(epoch, true);
+      session = await issueEpochSession(epoch);
+
+      // The entry's weight has to land in the tree
+      await expectError(() => buyEntry(epoch, session, Array.from(randomBytes(32)), weights[0]), "MissingWeightTree");
+
+      for (const weight of weights) {
+        await withRetry(() => buyEntry(epoch, session, Array.from(randomBytes(32)), weight, tree));
+      }
+
+      const treeInfo = await withRetry(() => erConnection.getAccountInfo(tree));
+      const treeState: any = erProgram.coder.accounts.decode("weightTree", treeInfo!.data);
+      expect(treeState.totalWeight.toNumber()).to.equal(totalWeight);
+
+      const ticketInfo = await withRetry(() => erConnection.getAccountInfo(ticket));
+      const ticketState: any = erProgram.coder.accounts.decode("playerTicket", ticketInfo!.data);
+      expect(ticketState.entries.map((e: any) => e.weight.toNumber())).to.deep.equal(weights);
+      expect(ticketState.balance.toNumber()).to.equal(0);
+    });
+
+    it("Draws distinct winners from the tree and pays them on L1 (Devnet)", async () => {
+      const drawn = await drawOnEr(epoch, drawAt);
+      const winningIds: number[] = drawn.winningTicketIds.map((id: BN) => id.toNumber());
+      console.log("Weighted winners:", winningIds);
+      expect([...winningIds].sort()).to.deep.equal([0, 1]);
+
+      // Winners' weight is only held out during the draw
+      const treeInfo = await withRetry(() => erConnection.getAccountInfo(tree));
+      const treeState: any = erProgram.coder.accounts.decode("weightTree", treeInfo!.data);
+      expect(treeState.totalWeight.toNumber()).to.equal(totalWeight);
+
+      await undelegateEpoch(epoch, true);
+      for (let tier = 0; tier < winningIds.length; tier++) {
+        await withRetry(() => l1Program.methods
+          .claimPrize(epoch, tier)
+          .accounts({
+            lotteryPool: pool,
+            playerTicket: ticket,
+            winner: wallet.publicKey,
+            systemProgram: anchor.web3.SystemProgram.programId,
+          } as any)
+          .rpc());
+      }
+      const poolState = await withRetry(() => l1Program.account.lotteryPool.fetch(pool));
+      expect(poolState.prizePoolSol.toNumber()).to.equal(prizePool.toNumber());
+      expect(poolState.claimedTiers).to.equal(0b11);
+
+      await fundIfEmpty(treasury.publicKey);
+      await fundIfEmpty(feeRecipient.publicKey);
+      await withRetry(() => l1Program.methods
+        .withdrawTaxes(epoch)
+        .accounts({
+          lotteryPool: pool,
+          authority: wallet.publicKey,
+          treasury: treasury.publicKey,
+          feeRecipient: feeRecipient.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        } as any)
+        .rpc());
+
+      // The tree goes with the pool, so it cannot be left behind
+      await expectError(() => l1Program.methods
+        .closeLotteryPool(epoch)
+        .accounts({
+          lotteryPool: pool,
+          authority: wallet.publicKey,
+          rentPayer: wallet.publicKey,
+          poolVault: null,
+          tokenProgram: null,
+          weightTree: null,
+        } as any)
+        .rpc(), "MissingWeightTree");
+      await closeEpoch(epoch, { weightTree: tree });
+      expect(await l1Connection.getAccountInfo(tree)).to.equal(null);
+      await closeEpochSession(session);
+    });
+  });
+
+  /*
+   * Token-denominated epochs: an SPL Token mint and a Token-2022 mint with a transfer fee
+  */
+
+  describe("Token-denominated epochs", () => {
+    const payer = (wallet as any).payer;
+    const decimals = 6;
+    const vaultPdaFor = (pool: PublicKey) =>
+      PublicKey.findProgramAddressSync([Buffer.from("pool_vault"), pool.toBuffer()], l1Program.programId)[0];
+    const tokenBalance = async (address: PublicKey, tokenProgram: PublicKey) =>
+      new BN((await getAccount(l1Connection, address, "confirmed", tokenProgram)).amount.toString());
+    const ataFor = async (mint: PublicKey, owner: PublicKey, tokenProgram: PublicKey) =>
+      (await getOrCreateAssociatedTokenAccount(
+        l1Connection, payer, mint, owner, false, "confirmed", undefined, tokenProgram
+      )).address;
+    // Token-2022 rounds the withheld fee up
+    const transferFee = (amount: BN, feeBps: number) => amount.muln(feeBps).addn(9_999).divn(10_000);
+
+    const initVault = async (epoch: BN, pool: PublicKey, mint: PublicKey, tokenProgram: PublicKey) => {
+      const vault = vaultPdaFor(pool);
+      await withRetry(() => l1Program.methods
+        .initPoolVault(epoch)
+        .accounts({
+          lotteryPool: pool,
+          authority: wallet.publicKey,
+          mint,
+          poolVault: vault,
+          tokenProgram,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        } as any)
+        .rpc());
+      const poolState = await withRetry(() => l1Program.account.lotteryPool.fetch(pool));
+      expect(poolState.mint.toBase58()).to.equal(mint.toBase58());
+      expect(poolState.tokenVault.toBase58()).to.equal(vault.toBase58());
+      return vault;
+    };
+
+    // 1% transfer fee, withheld in the receiving account
+    const feeBps = 100;
+    // Token-2022 mint with a transfer fee, and the wallet's funded account of it
+    const createFeeMint = async () => {
+      const mintKeypair = Keypair.generate();
+      const mint = mintKeypair.publicKey;
+      const mintLen = getMintLen([ExtensionType.TransferFeeConfig]);
+      const createMintTx = new anchor.web3.Transaction().add(
+        anchor.web3.SystemProgram.createAccount({
+          fromPubkey: wallet.publicKey,
+          newAccountPubkey: mint,
+          space: mintLen,
+          lamports: await l1Connection.getMinimumBalanceForRentExemption(mintLen),
+          programId: TOKEN_2022_PROGRAM_ID,
+        }),
+        createInitializeTransferFeeConfigInstruction(
+          mint, wallet.publicKey, wallet.publicKey, feeBps, BigInt(1_000_000_000), TOKEN_2022_PROGRAM_ID
+        ),
+        createInitializeMintInstruction(mint, decimals, wallet.publicKey, null, TOKEN_2022_PROGRAM_ID)
+      );
+      await anchor.web3.sendAndConfirmTransaction(l1Connection, createMintTx, [payer, mintKeypair], {
+        commitment: "confirmed",
+      });
+      const buyerAta = await ataFor(mint, wallet.publicKey, TOKEN_2022_PROGRAM_ID);
+      await mintTo(l1Connection, payer, mint, buyerAta, payer, 1_000_000_000, [], undefined, TOKEN_2022_PROGRAM_ID);
+      return { mint, buyerAta };
+    };
+
+    it("Runs an SPL Token epoch from purchase to tax withdrawal (Devnet)", async () => {
+      const mint = await createMint(l1Connection, payer, wallet.publicKey, null, decimals);
+      const buyerAta = await ataFor(mint, wallet.publicKey, TOKEN_PROGRAM_ID);
+      const treasuryAta = await ataFor(mint, treasury.publicKey, TOKEN_PROGRAM_ID);
+      const feeRecipientAta = await ataFor(mint, feeRecipient.publicKey, TOKEN_PROGRAM_ID);
+      await mintTo(l1Connection, payer, mint, buyerAta, payer, 1_000_000_000);
+
+      const { epoch, pool, ticket, params } = await initStandaloneEpoch();
+      const vault = await initVault(epoch, pool, mint, TOKEN_PROGRAM_ID);
+      const tokenAccounts = { mint, poolVault: vault, tokenProgram: TOKEN_PROGRAM_ID };
+
+      const credits = new BN(2);
+      const total = basePrice.mul(credits);
+      const tax = total.muln(purchaseFeeBps).divn(10_000);
+      const net = total.sub(tax);
+
+      // Paying in lamports is not an option once the pool is denominated
+      await expectError(() => l1Program.methods
+        .buyTicketCredits(epoch, credits, total)
+        .accounts({
+          lotteryPool: pool,
+          playerTicket: ticket,
+          buyer: wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        } as any)
+        .rpc(), "MissingTokenAccounts");
+
+      await withRetry(() => l1Program.methods
+        .buyTicketCredits(epoch, credits, total)
+        .accounts({
+          lotteryPool: pool,
+          playerTicket: ticket,
+          buyer: wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          buyerTokenAccount: buyerAta,
+          ...tokenAccounts,
+        } as any)
+        .rpc());
+      expect((await tokenBalance(vault, TOKEN_PROGRAM_ID)).toNumber()).to.equal(total.toNumber());
+      let poolState = await withRetry(() => l1Program.account.lotteryPool.fetch(pool));
+      expect(poolState.totalStakedSol.toNumber()).to.equal(net.toNumber());
+      expect(poolState.taxTreasurySol.toNumber()).to.equal(tax.toNumber());
+
+      // Entries and the draw never touch the vault
+      await delegateEpoch(epoch);
+      const session = await issueEpochSession(epoch);
+      for (let i = 0; i < credits.toNumber(); i++) {
+        await withRetry(() => buyEntry(epoch, session, Array.from(randomBytes(32))));
+      }
+      await drawOnEr(epoch, params.drawAt);
+      await undelegateEpoch(epoch);
+
+      // The single tier pays out the whole prize pool, less the payout fee
+      const payoutTax = net.muln(payoutFeeBps).divn(10_000);
+      const buyerBefore = await tokenBalance(buyerAta, TOKEN_PROGRAM_ID);
+      await withRetry(() => l1Program.methods
+        .claimPrize(epoch, 0)
+        .accounts({
+          lotteryPool: pool,
+          playerTicket: ticket,
+          winner: wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          winnerTokenAccount: buyerAta,
+          ...tokenAccounts,
+        } as any)
+        .rpc());
+      const buyerAfter = await tokenBalance(buyerAta, TOKEN_PROGRAM_ID);
+      expect(buyerAfter.sub(buyerBefore).toNumber()).to.equal(net.sub(payoutTax).toNumber());
+
+      const taxes = tax.add(payoutTax);
+      const protocolFee = taxes.muln(protocolFeeBps).divn(10_000);
+      await fundIfEmpty(treasury.publicKey);
+      await fundIfEmpty(feeRecipient.publicKey);
+      await withRetry(() => l1Program.methods
+        .withdrawTaxes(epoch)
+        .accounts({
+          lotteryPool: pool,
+          authority: wallet.publicKey,
+          treasury: treasury.publicKey,
+          feeRecipient: feeRecipient.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          treasuryTokenAccount: treasuryAta,
+          feeRecipientTokenAccount: feeRecipientAta,
+          ...tokenAccounts,
+        } as any)
+        .rpc());
+      expect((await tokenBalance(treasuryAta, TOKEN_PROGRAM_ID)).toNumber()).to.equal(taxes.sub(protocolFee).toNumber());
+      expect((await tokenBalance(feeRecipientAta, TOKEN_PROGRAM_ID)).toNumber()).to.equal(protocolFee.toNumber());
+      expect((await tokenBalance(vault, TOKEN_PROGRAM_ID)).toNumber()).to.equal(0);
+      poolState = await withRetry(() => l1Program.account.lotteryPool.fetch(pool));
+      expect(poolState.totalStakedSol.toNumber()).to.equal(0);
+      expect(poolState.taxTreasurySol.toNumber()).to.equal(0);
+
+      // The empty vault is closed with the pool
+      await closeEpoch(epoch, { poolVault: vault, tokenProgram: TOKEN_PROGRAM_ID });
+      expect(await l1Connection.getAccountInfo(vault)).to.equal(null);
+      await closeEpochSession(session);
+    });
+
+    it("Books Token-2022 purchases and refunds on what actually arrives (Devnet)", async () => {
+      const { mint, buyerAta } = await createFeeMint();
+
+      const { epoch, pool, ticket } = await initStandaloneEpoch();
+      const vault = await initVault(epoch, pool, mint, TOKEN_2022_PROGRAM_ID);
+      const tokenAccounts = { mint, poolVault: vault, tokenProgram: TOKEN_2022_PROGRAM_ID };
+
+      const credits = new BN(3);
+      const total = basePrice.mul(credits);
+      const received = total.sub(transferFee(total, feeBps));
+      const tax = received.muln(purchaseFeeBps).divn(10_000);
+
+      const purchaseSig = await withRetry(() => l1Program.methods
+        .buyTicketCredits(epoch, credits, total)
+        .accounts({
+          lotteryPool: pool,
+          playerTicket: ticket,
+          buyer: wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          buyerTokenAccount: buyerAta,
+          ...tokenAccounts,
+        } as any)
+        .rpc());
+
+      // The buyer is charged the quote; fee and stake are booked on what the vault received
+      const [purchased] = await withRetry(() => cpiEvents(purchaseSig));
+      expect(purchased.data.totalPrice.toNumber()).to.equal(total.toNumber());
+      expect(purchased.data.received.toNumber()).to.equal(received.toNumber());
+      expect(purchased.data.tax.toNumber()).to.equal(tax.toNumber());
+      expect((await tokenBalance(vault, TOKEN_2022_PROGRAM_ID)).toNumber()).to.equal(received.toNumber());
+
+      let poolState = await withRetry(() => l1Program.account.lotteryPool.fetch(pool));
+      expect(poolState.totalStakedSol.toNumber()).to.equal(received.sub(tax).toNumber());
+      expect(poolState.taxTreasurySol.toNumber()).to.equal(tax.toNumber());
+      const ticketState = await withRetry(() => l1Program.account.playerTicket.fetch(ticket));
+      expect(ticketState.paidSol.toNumber()).to.equal(received.toNumber());
+      expect(ticketState.paidTaxSol.toNumber()).to.equal(tax.toNumber());
+
+      // Cancelling refunds everything the pool received, fee included
+      await withRetry(() => l1Program.methods
+        .cancelEpoch(epoch)
+        .accounts({ lotteryPool: pool, authority: wallet.publicKey } as any)
+        .rpc());
+      const buyerBefore = await tokenBalance(buyerAta, TOKEN_2022_PROGRAM_ID);
+      await withRetry(() => l1Program.methods
+        .claimRefund(epoch)
+        .accounts({
+          lotteryPool: pool,
+          playerTicket: ticket,
+          player: wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          playerTokenAccount: buyerAta,
+          ...tokenAccounts,
+        } as any)
+        .rpc());
+      const buyerAfter = await tokenBalance(buyerAta, TOKEN_2022_PROGRAM_ID);
+      expect(buyerAfter.sub(buyerBefore).toNumber()).to.equal(received.sub(transferFee(received, feeBps)).toNumber());
+      expect((await tokenBalance(vault, TOKEN_2022_PROGRAM_ID)).toNumber()).to.equal(0);
+
+      poolState = await withRetry(() => l1Program.account.lotteryPool.fetch(pool));
+      expect(poolState.refundedSol.toNumber()).to.equal(received.toNumber());
+      expect(poolState.totalStakedSol.toNumber()).to.equal(0);
+      expect(poolState.taxTreasurySol.toNumber()).to.equal(0);
+
+      // Token-2022 only closes accounts whose withheld fees have been harvested
+      await harvestWithheldTokensToMint(l1Connection, payer, mint, [vault], undefined, TOKEN_2022_PROGRAM_ID);
+      await closeEpoch(epoch, { poolVault: vault, tokenProgram: TOKEN_2022_PROGRAM_ID });
+      expect(await l1Connection.getAccountInfo(vault)).to.equal(null);
+    });
+
+    it("Sweeps and rolls a Token-2022 epoch's prizes on, booking what arrives (Devnet)", async () => {
+      const { mint, buyerAta } = await createFeeMint();
+      const treasuryAta = await ataFor(mint, treasury.publicKey, TOKEN_2022_PROGRAM_ID);
+      const feeRecipientAta = await ataFor(mint, feeRecipient.publicKey, TOKEN_2022_PROGRAM_ID);
+
+      // Two tiers and a single entry: tier 0 is left unclaimed and swept into the
+      // next epoch, tier 1 goes undrawn and rolls over into it
+      const tiers = [6_000, 4_000];
+      const { epoch, pool, ticket, params } = await initStandaloneEpoch(
+        { prizeTiers: tiers, forfeitPolicy: { nextEpoch: {} }, claimWindowSecs: new BN(5) },
+        180,
+        new BN(randomBytes(4).readUInt32LE(0) >>> 1 || 1)
+      );
+      const next = await initStandaloneEpoch({}, 180, epoch.addn(1));
+      const vault = await initVault(epoch, pool, mint, TOKEN_2022_PROGRAM_ID);
+      const nextVault = await initVault(next.epoch, next.pool, mint, TOKEN_2022_PROGRAM_ID);
+      const tokenAccounts = { mint, poolVault: vault, tokenProgram: TOKEN_2022_PROGRAM_ID };
+
+      const received = basePrice.sub(transferFee(basePrice, feeBps));
+      const tax = received.muln(purchaseFeeBps).divn(10_000);
+      const net = received.sub(tax);
+      await withRetry(() => l1Program.methods
+        .buyTicketCredits(epoch, new BN(1), basePrice)
+        .accounts({
+          lotteryPool: pool,
+          playerTicket: ticket,
+          buyer: wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          buyerTokenAccount: buyerAta,
+          ...tokenAccounts,
+        } as any)
+        .rpc());
+
+      await delegateEpoch(epoch);
+      const session = await issueEpochSession(epoch);
+      await withRetry(() => buyEntry(epoch, session, Array.from(randomBytes(32))));
+      await drawOnEr(epoch, params.drawAt);
+      await undelegateEpoch(epoch);
+
+      // Each move credits the next pool with what reaches its vault, not what left this one
+      const drawn = await withRetry(() => l1Program.account.lotteryPool.fetch(pool));
+      await waitUntil(drawn.claimDeadline.toNumber());
+      const forfeited = net.muln(tiers[0]).divn(10_000);
+      const sweptIn = forfeited.sub(transferFee(forfeited, feeBps));
+      await withRetry(() => l1Program.methods
+        .sweepUnclaimed(epoch)
+        .accounts({
+          lotteryPool: pool,
+          authority: wallet.publicKey,
+          nextPool: next.pool,
+          nextPoolVault: nextVault,
+          ...tokenAccounts,
+        } as any)
+        .rpc());
+      let nextState = await withRetry(() => l1Program.account.lotteryPool.fetch(next.pool));
+      expect(nextState.totalStakedSol.toNumber()).to.equal(sweptIn.toNumber());
+      expect(nextState.rolledInSol.toNumber()).to.equal(sweptIn.toNumber());
+
+      const leftover = net.sub(forfeited);
+      const rolledIn = leftover.sub(transferFee(leftover, feeBps));
+      await withRetry(() => l1Program.methods
+        .rolloverJackpot(epoch, next.epoch)
+        .accounts({
+          lotteryPool: pool,
+          nextPool: next.pool,
+          authority: wallet.publicKey,
+          nextPoolVault: nextVault,
+          ...tokenAccounts,
+        } as any)
+        .rpc());
+      const arrived = sweptIn.add(rolledIn);
+      expect((await tokenBalance(nextVault, TOKEN_2022_PROGRAM_ID)).toNumber()).to.equal(arrived.toNumber());
+      nextState = await withRetry(() => l1Program.account.lotteryPool.fetch(next.pool));
+      expect(nextState.totalStakedSol.toNumber()).to.equal(arrived.toNumber());
+      expect(nextState.rolledInSol.toNumber()).to.equal(arrived.toNumber());
+      const poolState = await withRetry(() => l1Program.account.lotteryPool.fetch(pool));
+      expect(poolState.totalStakedSol.toNumber()).to.equal(0);
+      expect(poolState.rolledOverTo.toString()).to.equal(next.epoch.toString());
+
+      await withRetry(() => l1Program.methods
+        .withdrawTaxes(epoch)
+        .accounts({
+          lotteryPool: pool,
+          authority: wallet.publicKey,
+          treasury: treasury.publicKey,
+          feeRecipient: feeRecipient.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          treasuryTokenAccount: treasuryAta,
+          feeRecipientTokenAccount: feeRecipientAta,
+          ...tokenAccounts,
+        } as any)
+        .rpc());
+      await harvestWithheldTokensToMint(l1Connection, payer, mint, [vault], undefined, TOKEN_2022_PROGRAM_ID);
+      await closeEpoch(epoch, { poolVault: vault, tokenProgram: TOKEN_2022_PROGRAM_ID });
+      await closeEpochSession(session);
+      // The next epoch keeps the carried stake; its throwaway mint is never drawn
+    });
+  });
+
+  /*
+   * Pick-number epoch: committed numbers revealed after the draw
+  */
+
+  describe("Pick-number epoch", () => {
+    // With a single number to pick, both entries match the draw and the tie-break decides
+    const gameMode = { pickNumber: { maxNumber: 1 } };
+    const revealWindow = new BN(240);
+    const salts = [randomBytes(32), randomBytes(32)];
+    const NO_WINNER = "18446744073709551615";
+    // Mirrors `ticket_commitment`: sha256(number_le || salt)
+    const commitment = (number: number, salt: Buffer) => {
+      const numberLe = Buffer.alloc(4);
+      numberLe.writeUInt32LE(number);
+      return Array.from(createHash("sha256").update(numberLe).update(salt).digest());
+    };
+    const reveal = (epoch: BN, ticketId: number, number: number, salt: Buffer) =>
+      l1Program.methods
+        .revealTicket(epoch, new BN(ticketId), number, Array.from(salt))
+        .accounts({
+          lotteryPool: poolPdaFor(epoch),
+          playerTicket: playerTicketPdaFor(epoch),
+          owner: wallet.publicKey,
+        } as any)
+        .rpc();
+
+    it("Reveals committed numbers and awards ties to the lowest ticket id (Devnet)", async () => {
+      const { epoch, pool, ticket, params } = await initStandaloneEpoch({ gameMode, revealWindowSecs: revealWindow });
+      await withRetry(() => l1Program.methods
+        .buyTicketCredits(epoch, new BN(2), basePrice.muln(2))
+        .accounts({
+          lotteryPool: pool,
+          playerTicket: ticket,
+          buyer: wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        } as any)
+        .rpc());
+
+      await delegateEpoch(epoch);
+      const session = await issueEpochSession(epoch);
+      for (const salt of salts) {
+        await withRetry(() => buyEntry(epoch, session, commitment(1, salt)));
+      }
+
+      const drawn = await drawOnEr(epoch, params.drawAt);
+      expect(drawn.winningNumbers).to.deep.equal([1]);
+      // Nobody holds the tier until an entry reveals its number
+      expect(drawn.winningTicketIds.map((id: BN) => id.toString())).to.deep.equal([NO_WINNER]);
+      await undelegateEpoch(epoch);
+
+      // The commitment binds both the number and the salt
+      await expectError(() => reveal(epoch, 0, 1, salts[1]), "InvalidReveal");
+      await expectError(() => reveal(epoch, 0, 2, salts[0]), "InvalidReveal");
+
+      // Ticket #1 reveals first and holds the tier...
+      await withRetry(() => reveal(epoch, 1, 1, salts[1]));
+      let poolState = await withRetry(() => l1Program.account.lotteryPool.fetch(pool));
+      expect(poolState.winningTicketIds.map((id: BN) => id.toNumber())).to.deep.equal([1]);
+      await expectError(() => reveal(epoch, 1, 1, salts[1]), "TicketAlreadyRevealed");
+
+      // ...until the lower ticket #0 reveals the same number
+      await withRetry(() => reveal(epoch, 0, 1, salts[0]));
+      poolState = await withRetry(() => l1Program.account.lotteryPool.fetch(pool));
+      expect(poolState.winningTicketIds.map((id: BN) => id.toNumber())).to.deep.equal([0]);
+      const ticketState = await withRetry(() => l1Program.account.playerTicket.fetch(ticket));
+      expect(ticketState.entries.map((e: any) => e.revealed)).to.deep.equal([1, 1]);
+
+      const claim = () => l1Program.methods
+        .claimPrize(epoch, 0)
+        .accounts({
+          lotteryPool: pool,
+          playerTicket: ticket,
+          winner: wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        } as any)
+        .rpc();
+      // Claims wait for the reveal window, so a later lower reveal cannot be preempted
+      await expectError(claim, "RevealWindowOpen");
+      await waitUntil(poolState.revealDeadline.toNumber());
+      await withRetry(claim);
+      poolState = await withRetry(() => l1Program.account.lotteryPool.fetch(pool));
+      expect(poolState.claimedTiers).to.equal(1);
+
+      await fundIfEmpty(treasury.publicKey);
+      await fundIfEmpty(feeRecipient.publicKey);
+      await withRetry(() => l1Program.methods
+        .withdrawTaxes(epoch)
+        .accounts({
+          lotteryPool: pool,
+          authority: wallet.publicKey,
+          treasury: treasury.publicKey,
+          feeRecipient: feeRecipient.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        } as any)
+        .rpc());
+      await closeEpoch(epoch);
+      await closeEpochSession(session);
+    });
+  });
+
+  /*
+   * Pick-N epoch: entries pick numbers up front and register their matches after the draw
+  */
+
+  describe("Pick-N epoch", () => {
+    // Two of three numbers are drawn, so of the three possible pairs exactly one
+    // matches both (tier 0) and the other two match one each (tier 1, split)
+    const gameMode = { pickN: { picks: 2, maxNumber: 3 } };
+    const tiers = [7_000, 3_000];
+    const pairs = [[1, 2], [1, 3], [2, 3]];
+    const pickData = (numbers: number[]) => [...numbers, ...new Array(32 - numbers.length).fill(0)];
+
+    it("Registers matches and splits each tier among its entries (Devnet)", async () => {
+      const { epoch, pool, ticket, params } = await initStandaloneEpoch({
+        gameMode,
+        prizeTiers: tiers,
+        revealWindowSecs: new BN(240),
+      });
+      // Each Pick-N entry takes one credit, so credits past the entry limit could never be spent
+      await expectError(() => l1Program.methods
+        .buyTicketCredits(epoch, new BN(17), basePrice.muln(17))
+        .accounts({
+          lotteryPool: pool,
+          playerTicket: ticket,
+          buyer: wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        } as any)
+        .rpc(), "TicketLimitReached");
+
+      const credits = new BN(pairs.length);
+      const total = basePrice.mul(credits);
+      const net = total.sub(total.muln(purchaseFeeBps).divn(10_000));
+      await withRetry(() => l1Program.methods
+        .buyTicketCredits(epoch, credits, total)
+        .accounts({
+          lotteryPool: pool,
+          playerTicket: ticket,
+          buyer: wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        } as any)
+        .rpc());
+
+      await delegateEpoch(epoch);
+      const session = await issueEpochSession(epoch);
+      // Picks are distinct numbers in 1..=maxNumber, zero-padded
+      const padded = pickData([1, 2]);
+      padded[31] = 1;
+      for (const bad of [pickData([1, 1]), pickData([0, 2]), pickData([1, 4]), padded]) {
+        await expectError(() => buyEntry(epoch, session, bad), "InvalidTicketData");
+      }
+      for (const pair of pairs) {
+        await withRetry(() => buyEntry(epoch, session, pickData(pair)));
+      }
+
+      const drawn = await drawOnEr(epoch, params.drawAt);
+      const winningNumbers: number[] = drawn.winningNumbers;
+      expect(winningNumbers.length).to.equal(2);
+      const expectedMatches = pairs.map(pair => pair.filter(n => winningNumbers.includes(n)).length);
+      expect([...expectedMatches].sort()).to.deep.equal([1, 1, 2]);
+      await undelegateEpoch(epoch);
+
+      const register = () => l1Program.methods
+        .registerTicket(epoch)
+        .accounts({
+          lotteryPool: pool,
+          playerTicket: ticket,
+          owner: wallet.publicKey,
+        } as any)
+        .rpc();
+      const registerSig = await withRetry(register);
+      const [registered] = (await withRetry(() => cpiEvents(registerSig))).filter(e => e.name === "ticketRegistered");
+      expect(Array.from(registered.data.matches)).to.deep.equal(expectedMatches);
+      await expectError(register, "TicketAlreadyRegistered");
+
+      let poolState = await withRetry(() => l1Program.account.lotteryPool.fetch(pool));
+      expect(poolState.tierWinners).to.deep.equal([1, 2]);
+
+      const claim = (tier: number) => l1Program.methods
+        .claimPrize(epoch, tier)
+        .accounts({
+          lotteryPool: pool,
+          playerTicket: ticket,
+          winner: wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        } as any)
+        .rpc();
+      await waitUntil(poolState.revealDeadline.toNumber());
+
+      // Tier 0 has a single winner; tier 1's share is split between two entries,
+      // both paid by the one claim
+      const topShare = net.muln(tiers[0]).divn(10_000);
+      const splitShare = net.muln(tiers[1]).divn(10_000).divn(2);
+      const topSig = await withRetry(() => claim(0));
+      const [top] = (await withRetry(() => cpiEvents(topSig))).filter(e => e.name === "prizeClaimed");
+      expect(top.data.entries).to.equal(1);
+      expect(top.data.payout.toNumber()).to.equal(topShare.toNumber());
+      expect(top.data.ticketId.toNumber()).to.equal(expectedMatches.indexOf(2));
+      const splitSig = await withRetry(() => claim(1));
+      const [split] = (await withRetry(() => cpiEvents(splitSig))).filter(e => e.name === "prizeClaimed");
+      expect(split.data.entries).to.equal(2);
+      expect(split.data.payout.toNumber()).to.equal(splitShare.muln(2).toNumber());
+      expect(split.data.ticketId.toNumber()).to.equal(expectedMatches.indexOf(1));
+
+      await expectError(() => claim(1), "PrizeAlreadyClaimed");
+      await expectError(() => claim(2), "InvalidPrizeTier");
+      poolState = await withRetry(() => l1Program.account.lotteryPool.fetch(pool));
+      expect(poolState.tierClaims).to.deep.equal([1, 2]);
+      const ticketState = await withRetry(() => l1Program.account.playerTicket.fetch(ticket));
+      expect(ticketState.claimedTiers).to.equal(0b11);
+      expect(ticketState.entries.map((e: any) => e.matches)).to.deep.equal(expectedMatches);
+      // Only the rounding dust of the split is left with the pool
+      expect(poolState.totalStakedSol.toNumber()).to.equal(net.sub(topShare).sub(splitShare.muln(2)).toNumber());
+
+      await fundIfEmpty(treasury.publicKey);
+      await fundIfEmpty(feeRecipient.publicKey);
+      await withRetry(() => l1Program.methods
+        .withdrawTaxes(epoch)
+        .accounts({
+          lotteryPool: pool,
+          authority: wallet.publicKey,
+          treasury: treasury.publicKey,
+          feeRecipient: feeRecipient.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        } as any)
+        .rpc());
+      await closeEpoch(epoch);
+      await closeEpochSession(session);
+    });
+  });
+
+  /*
+   * Cancelled epoch: every player gets back what they paid, tax and unused credits included
+  */
+
+  describe("Cancelled epoch", () => {
+    it("Refunds paid credits including the tax, once (Devnet)", async () => {
+      const { epoch, pool, ticket } = await initStandaloneEpoch();
+      const credits = new BN(3);
+      const total = basePrice.mul(credits);
+      const tax = total.muln(purchaseFeeBps).divn(10_000);
+      await withRetry(() => l1Program.methods
+        .buyTicketCredits(epoch, credits, total)
+        .accounts({
+          lotteryPool: pool,
+          playerTicket: ticket,
+          buyer: wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        } as any)
+        .rpc());
+      const bought = await withRetry(() => l1Program.account.playerTicket.fetch(ticket));
+      expect(bought.paidSol.toNumber()).to.equal(total.toNumber());
+      expect(bought.paidTaxSol.toNumber()).to.equal(tax.toNumber());
+      expect(bought.balance.toNumber()).to.equal(credits.toNumber());
+
+      const claimRefund = () => l1Program.methods
+        .claimRefund(epoch)
+        .accounts({
+          lotteryPool: pool,
+          playerTicket: ticket,
+          player: wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        } as any)
+        .rpc();
+      // A live epoch only refunds after the draw has been abandoned for REFUND_TIMEOUT_SECS
+      await expectError(claimRefund, "RefundNotAvailable");
+
+      await withRetry(() => l1Program.methods
+        .cancelEpoch(epoch)
+        .accounts({ lotteryPool: pool, authority: wallet.publicKey } as any)
+        .rpc());
+      await expectError(() => l1Program.methods
+        .cancelEpoch(epoch)
+        .accounts({ lotteryPool: pool, authority: wallet.publicKey } as any)
+        .rpc(), "EpochCancelled");
+
+      // Every credit is still unspent; the refund covers them and the purchase tax
+      const poolLamportsBefore = await l1Connection.getBalance(pool);
+      const refundSig = await withRetry(claimRefund);
+      const poolLamportsAfter = await l1Connection.getBalance(pool);
+      expect(poolLamportsBefore - poolLamportsAfter).to.equal(total.toNumber());
+
+      const [refunded] = (await withRetry(() => cpiEvents(refundSig))).filter(e => e.name === "refundClaimed");
+      expect(refunded.data.amount.toNumber()).to.equal(total.toNumber());
+
+      const poolState = await withRetry(() => l1Program.account.lotteryPool.fetch(pool));
+      expect(poolState.isCancelled).to.equal(true);
+      expect(poolState.refundedSol.toNumber()).to.equal(total.toNumber());
+      expect(poolState.totalStakedSol.toNumber()).to.equal(0);
+      expect(poolState.taxTreasurySol.toNumber()).to.equal(0);
+      const ticketState = await withRetry(() => l1Program.account.playerTicket.fetch(ticket));
+      expect(ticketState.paidSol.toNumber()).to.equal(0);
+      expect(ticketState.paidTaxSol.toNumber()).to.equal(0);
+      expect(ticketState.balance.toNumber()).to.equal(0);
+
+      await expectError(claimRefund, "NothingToRefund");
+      await closeEpoch(epoch);
+    });
   });
 });